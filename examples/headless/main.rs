@@ -0,0 +1,56 @@
+use std::rc::Rc;
+
+use gputext::{
+    core::{
+        font_manager::text_layout::TextAlignment,
+        gui_manager::text::{TextAnchor, TextStyle},
+        Controller,
+    },
+    settings::GameSettings,
+};
+
+// Usage: cargo run --example headless -- "Hello world!" text.png [1280x720] [scale factor]
+fn main() {
+    env_logger::init();
+    let mut args = std::env::args().skip(1);
+    let content = args.next().unwrap_or_else(|| "Hello world!".to_string());
+    let output = args.next().unwrap_or_else(|| "text.png".to_string());
+    let size = args.next().map(|size| {
+        let (width, height) = size.split_once('x').expect("Size should be WIDTHxHEIGHT!");
+        [
+            width.parse::<u32>().unwrap(),
+            height.parse::<u32>().unwrap(),
+        ]
+    });
+    let scale_factor = args
+        .next()
+        .map_or(1.0, |scale| scale.parse::<f64>().unwrap());
+
+    let mut controller = Controller::new_headless(Rc::new(GameSettings::new()));
+    let index = controller.add_text(
+        content,
+        [960.0, 540.0],
+        TextStyle {
+            font_size: 64.0,
+            anchor: TextAnchor::Center,
+            max_width: Some(1600.0),
+            alignment: TextAlignment::Center,
+            replacement_char: Some('?'),
+            ..Default::default()
+        },
+    );
+    if let Some([width, height]) = size {
+        controller.resize(width, height, scale_factor);
+        let center = [
+            (width as f64 / scale_factor / 2.0) as f32,
+            (height as f64 / scale_factor / 2.0) as f32,
+        ];
+        controller.move_text(index, center, TextAnchor::Center);
+    }
+    let missing_chars = controller.missing_chars(index);
+    if !missing_chars.is_empty() {
+        println!("Missing characters: {:?}", missing_chars);
+    }
+    controller.draw();
+    controller.save_frame(output);
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use gputext::core::font_manager::{
+    font_graphics::{CoverageCurve, FillRule},
+    font_info_saving::PreprocessParams,
+    font_rasterizer::rasterize_glyph,
+    get_font_drawing_data,
+};
+
+// Usage: cargo run --example rasterize -- A 24 [evenodd]
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let this_char = args.next().and_then(|s| s.chars().next()).unwrap_or('A');
+    let pixels_per_em: f32 = args.next().and_then(|s| s.parse().ok()).unwrap_or(24.0);
+    let fill_rule = match args.next().as_deref() {
+        Some("evenodd") => FillRule::EvenOdd,
+        _ => FillRule::NonZero,
+    };
+
+    let font_file = Path::new("data").join("eng1.ttf");
+    let font_data = std::fs::read(font_file).expect("Unable to open file.");
+    let font_face = owned_ttf_parser::Face::parse(&font_data, 0).expect("Unable to parse font.");
+    let drawing_data = get_font_drawing_data(&font_face, &PreprocessParams::default());
+
+    let glyph_id = font_face.glyph_index(this_char).expect("No such glyph.");
+    let Some(bitmap) = rasterize_glyph(
+        &drawing_data,
+        glyph_id.0 as u32,
+        pixels_per_em,
+        fill_rule,
+        CoverageCurve::default(),
+    ) else {
+        println!("Glyph {} has no outline.", glyph_id.0);
+        return;
+    };
+    println!(
+        "{}x{} pixels, bottom left at ({}, {}) from the glyph's",
+        bitmap.width, bitmap.height, bitmap.left, bitmap.bottom
+    );
+    for row in bitmap.rows() {
+        let line: String = row
+            .iter()
+            .map(|coverage| match (coverage * 4.0).round() as u32 {
+                0 => ' ',
+                1 => '.',
+                2 => ':',
+                3 => '+',
+                _ => '#',
+            })
+            .collect();
+        println!("{}", line);
+    }
+}
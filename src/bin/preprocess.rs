@@ -0,0 +1,204 @@
+use std::{path::PathBuf, time::Instant};
+
+use gputext::core::font_manager::{
+    font_collection::{find_face, list_axes, list_faces, FaceSelector, Variation},
+    font_info_saving::{cache_path, load_cache, save_cache, CacheHeader, PreprocessParams},
+    get_font_drawing_data_with_progress,
+};
+
+const USAGE: &str = "\
+Usage: preprocess [options] <font files...>
+
+Builds the glyph caches the game loads from data/font. The game loads caches
+made with any band and tolerance options, as long as the font file, face and
+variations match.
+
+Options:
+    --out <dir>              write caches to <dir> instead of data/font
+    --face <index or name>   face of font collections, the first by default
+    --var <axis=value>       axis coordinate of variable fonts, repeatable
+    --list                   only list the faces and axes of the font files
+    --min-bands <count>      fewest bands per glyph, 2 by default
+    --max-bands <count>      most bands per glyph, 16 by default
+    --curves-per-band <n>    curves per band before adding one, 8 by default
+    --tolerance <em>         max error of cubic to quadratic conversion
+    --check                  only check the caches are present and built with
+                             these options";
+
+struct Options {
+    font_paths: Vec<PathBuf>,
+    out_dir: Option<PathBuf>,
+    face: FaceSelector,
+    params: PreprocessParams,
+    check: bool,
+    list: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        font_paths: Vec::new(),
+        out_dir: None,
+        face: FaceSelector::default(),
+        params: PreprocessParams::default(),
+        check: false,
+        list: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--out" => options.out_dir = Some(PathBuf::from(value()?)),
+            "--face" => options.face = FaceSelector::parse(&value()?),
+            "--var" => {
+                let variation = value()?;
+                options.params.variations.push(
+                    Variation::parse(&variation)
+                        .ok_or_else(|| format!("Invalid axis coordinate {}", variation))?,
+                );
+            }
+            "--min-bands" => options.params.min_band_count = parse(&value()?)?,
+            "--max-bands" => options.params.max_band_count = parse(&value()?)?,
+            "--curves-per-band" => options.params.curves_per_band = parse(&value()?)?,
+            "--tolerance" => options.params.cubic_tolerance = parse(&value()?)?,
+            "--check" => options.check = true,
+            "--list" => options.list = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => options.font_paths.push(PathBuf::from(arg)),
+        }
+    }
+    if options.font_paths.is_empty() {
+        return Err("No font file given".to_string());
+    }
+    if options.params.curves_per_band == 0
+        || options.params.min_band_count == 0
+        || options.params.min_band_count > options.params.max_band_count
+    {
+        return Err("Invalid band limits".to_string());
+    }
+
+    Ok(options)
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {}", value))
+}
+
+// Usage: cargo run --release --bin preprocess -- data/chi1.ttf
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(error) => {
+            if !error.is_empty() {
+                eprintln!("{}\n", error);
+            }
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut stale = 0;
+    for font_path in options.font_paths.iter() {
+        let font_file = std::fs::read(font_path).expect("Unable to read font!");
+        if options.list {
+            println!("{}", font_path.display());
+            for face in list_faces(&font_file) {
+                println!(
+                    "    {}: {} ({} {})",
+                    face.index, face.full_name, face.family, face.style
+                );
+                let font_face = owned_ttf_parser::Face::parse(&font_file, face.index)
+                    .expect("Unable to parse font!");
+                for axis in list_axes(&font_face) {
+                    println!(
+                        "        {}: {} {} to {}, {} by default{}",
+                        String::from_utf8_lossy(&axis.axis),
+                        axis.name,
+                        axis.min_value,
+                        axis.max_value,
+                        axis.default_value,
+                        if axis.hidden { ", hidden" } else { "" }
+                    );
+                }
+            }
+            continue;
+        }
+
+        let Some(face_index) = find_face(&font_file, &options.face) else {
+            eprintln!("No face {} in {}", options.face, font_path.display());
+            std::process::exit(2);
+        };
+        let params = PreprocessParams {
+            face_index,
+            ..options.params.clone()
+        };
+        let mut font_face =
+            owned_ttf_parser::Face::parse(&font_file, face_index).expect("Unable to parse font!");
+        for variation in params.variations.iter() {
+            if variation.apply(&mut font_face).is_none() {
+                eprintln!("No axis for {} in {}", variation, font_path.display());
+                std::process::exit(2);
+            }
+        }
+        let save_path = match &options.out_dir {
+            Some(out_dir) => out_dir.join(cache_path(font_path, &params).file_name().unwrap()),
+            None => cache_path(font_path, &params),
+        };
+        let header = CacheHeader::new(&font_file, params.clone());
+
+        if options.check {
+            match load_cache(&save_path, &header) {
+                Some(_) => println!("{}: up to date", save_path.display()),
+                None => {
+                    println!("{}: missing or stale", save_path.display());
+                    stale += 1;
+                }
+            }
+            continue;
+        }
+
+        let start = Instant::now();
+        let data = get_font_drawing_data_with_progress(&font_face, &params, |done, total| {
+            // update once per percent
+            if done * 100 / total != (done - 1) * 100 / total || done == total {
+                eprint!("\r{}: {}/{} glyphs", font_path.display(), done, total);
+            }
+        });
+        eprintln!();
+        let elapsed = start.elapsed();
+        if let Some(save_dir) = save_path.parent() {
+            std::fs::create_dir_all(save_dir).expect("Unable to create cache directory!");
+        }
+        save_cache(&save_path, &header, &data);
+
+        let stats = data.stats();
+        println!("{} -> {}", font_path.display(), save_path.display());
+        println!("    Time: {:.2?}", elapsed);
+        println!(
+            "    Glyphs: {} ({} without outline)",
+            stats.glyphs, stats.empty_glyphs
+        );
+        println!(
+            "    Curves: {} (at most {} per glyph)",
+            stats.curves, stats.max_curves_per_glyph
+        );
+        println!(
+            "    Bands: {} (at most {} per direction and glyph)",
+            stats.bands, stats.max_band_count
+        );
+        println!(
+            "    Curves per band: {:.2} on average, {} at most",
+            stats.mean_band_size, stats.max_band_size
+        );
+        println!("    GPU data: {} bytes", stats.bytes);
+    }
+
+    if stale > 0 {
+        std::process::exit(1);
+    }
+}
@@ -3,6 +3,10 @@ struct VertexInput {
     @location(1) glyph_id: u32,
     @location(2) base_line: vec2<f32>,
     @location(3) pixels_per_em: f32,
+    @location(4) font_index: u32,
+    @location(5) color: vec4<f32>,
+    // offset in pixels with y pointing down, dilation and blur radius in pixels
+    @location(6) effect: vec4<f32>,
 };
 
 struct VertexOutput {
@@ -10,12 +14,16 @@ struct VertexOutput {
     @location(0) xy: vec2<f32>,
     @location(1) pixels_per_em: f32,
     @location(2) glyph_id: u32,
+    @location(3) color: vec4<f32>,
+    @location(4) effect: vec2<f32>,
 };
 
 struct FragmengInput {
     @location(0) position: vec2<f32>,
     @location(1) pixels_per_em: f32,
     @location(2) glyph_id: u32,
+    @location(3) color: vec4<f32>,
+    @location(4) effect: vec2<f32>,
 };
 
 struct GlyphData {
@@ -32,8 +40,19 @@ struct CurveInfo {
     p2: vec2<f32>,
 };
 
+// coverage curve, see `CoverageCurve`, whether the target encodes to sRGB
+// and whether the subpixels of the display are in BGR order
+struct ColorInfo {
+    gamma: f32,
+    contrast: f32,
+    srgb_target: f32,
+    subpixel_bgr: f32,
+};
+
 @group(0) @binding(0)
 var<uniform> window_size: vec2<f32>;
+@group(0) @binding(1)
+var<uniform> color_info: ColorInfo;
 
 @group(1) @binding(0)
 var<storage, read> font_info: array<GlyphData>;
@@ -43,27 +62,39 @@ var<storage, read> font_curves: array<CurveInfo>;
 var<storage, read> hband_curves: array<u32>;
 @group(1) @binding(3)
 var<storage, read> vband_curves: array<u32>;
+@group(1) @binding(4)
+var<storage, read> font_glyph_offsets: array<u32>;
 
 @vertex
 fn vs_main(input: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    out.glyph_id = input.glyph_id;
+    // index of the glyph among the glyphs of all fonts
+    out.glyph_id = font_glyph_offsets[input.font_index] + input.glyph_id;
     // Equivlent to:
     // let multiplier_x = input.pixels_per_em / window_size.x * 2.0;
     // let multiplier_y = input.pixels_per_em / window_size.y * 2.0;
     let multiplier = input.pixels_per_em * 2.0 / window_size;
-    let this_char_info = font_info[input.glyph_id];
+    let this_char_info = font_info[out.glyph_id];
     let scale_x = multiplier.x * this_char_info.width_in_em;
     let scale_y = multiplier.y * this_char_info.height_in_em;
     let scale_mat = mat3x3<f32>(scale_x, 0.0, 0.0, 0.0, scale_y, 0.0, 0.0, 0.0, 1.0);
-    let pos_scaled = scale_mat * input.position;
+    // The antialiased fringe of edges on the bounding box reaches half a
+    // pixel past it, outlines and shadows reach further. Grow the quad by
+    // that in screen space and move it by the shadow offset, in em.
+    let margin = (input.effect.z + input.effect.w + 0.5) / input.pixels_per_em;
+    let offset = vec2<f32>(input.effect.x, -input.effect.y) / input.pixels_per_em;
+    let grow = (input.position.xy * 2.0 - 1.0) * margin + offset;
+    let pos_scaled = scale_mat * input.position + vec3<f32>(grow * multiplier, 0.0);
     let move_mat = vec3<f32>(input.base_line.x, input.base_line.y, 0.0);
     out.pos = vec4<f32>(pos_scaled + move_mat, 1.0);
     // Equivlent to the following 2 lines:
     // let transform_mul = vec2<f32>(this_char_info.width_in_em / scale_x, this_char_info.height_in_em / scale_y);
     // out.xy = pos_scaled.xy * transform_mul;
-    out.xy = pos_scaled.xy / multiplier;
+    // the glyph is sampled where the shadow offset moved it from
+    out.xy = pos_scaled.xy / multiplier - offset;
+    out.effect = input.effect.zw / input.pixels_per_em;
     out.pixels_per_em = input.pixels_per_em;
+    out.color = input.color;
     return out;
 }
 
@@ -154,31 +185,239 @@ fn band_process(horizontal: bool, pixel: vec2<f32>, pixels_per_em: f32, band_ind
     return winding_number;
 }
 
+// Coverage of a winding number under a fill rule. Contours wound either way
+// count the same, fractions come from edges crossing the pixel.
+fn apply_fill_rule(winding_number: f32, even_odd: bool) -> f32 {
+    let winding = abs(winding_number);
+    if even_odd {
+        // 1 for odd windings and 0 for even ones, linear in between
+        return 1.0 - abs(1.0 - winding % 2.0);
+    }
+    return min(winding, 1.0);
+}
+
+// Coverage of the pixel at `position` by the glyph, in em from the bottom left
+// corner of its bounding box, from 0 to 1 before gamma.
+fn coverage(glyph_data: GlyphData, position: vec2<f32>, pixels_per_em: f32, even_odd: bool) -> f32 {
+    // positions past the bounding box use the edge bands, their curves don't
+    // cross the rays of such pixels
+    let last_band = f32(glyph_data.band_count - 1u);
+    let hband_num = u32(clamp(position.y / glyph_data.height_in_em * f32(glyph_data.band_count), 0.0, last_band));
+    let vband_num = u32(clamp(position.x / glyph_data.width_in_em * f32(glyph_data.band_count), 0.0, last_band));
+
+    let horizontal = band_process(true, position, pixels_per_em, glyph_data.hband_index, hband_num, glyph_data.curve_texel_index);
+    let vertical = band_process(false, position, pixels_per_em, glyph_data.vband_index, vband_num, glyph_data.curve_texel_index);
+
+    // Take the average of the horizontal and vertical results. The fill rule
+    // applies to each ray on its own, summing the winding numbers first would
+    // let overlapping contours and opposite signs of the rays cancel out.
+    return (apply_fill_rule(horizontal, even_odd) + apply_fill_rule(vertical, even_odd)) * 0.5;
+}
+
+// Distance from `position` to the curve from `p0` to `p2` with control point
+// `p1`, the closest of the roots of the derivative of the squared distance.
+fn bezier_distance(position: vec2<f32>, p0: vec2<f32>, p1: vec2<f32>, p2: vec2<f32>) -> f32 {
+    let a = p1 - p0;
+    let b = p0 - 2.0 * p1 + p2;
+    let d = p0 - position;
+    if dot(b, b) < 1e-10 {
+        // a line
+        let line = p2 - p0;
+        let t = clamp(-dot(d, line) / max(dot(line, line), 1e-10), 0.0, 1.0);
+        return length(d + line * t);
+    }
+    let c = a * 2.0;
+    let kk = 1.0 / dot(b, b);
+    let kx = kk * dot(a, b);
+    let ky = kk * (2.0 * dot(a, a) + dot(d, b)) / 3.0;
+    let kz = kk * dot(d, a);
+    let p = ky - kx * kx;
+    let q = kx * (2.0 * kx * kx - 3.0 * ky) + kz;
+    let h = q * q + 4.0 * p * p * p;
+    if h >= 0.0 {
+        let x = (vec2<f32>(sqrt(h), -sqrt(h)) - q) * 0.5;
+        let uv = sign(x) * pow(abs(x), vec2<f32>(1.0 / 3.0));
+        let t = clamp(uv.x + uv.y - kx, 0.0, 1.0);
+        return length(d + (c + b * t) * t);
+    }
+    let z = sqrt(-p);
+    let v = acos(q / (p * z * 2.0)) / 3.0;
+    let m = cos(v);
+    let n = sin(v) * 1.7320508;
+    let t = clamp(vec2<f32>(m + m, -n - m) * z - kx, vec2<f32>(0.0), vec2<f32>(1.0));
+    return min(length(d + (c + b * t.x) * t.x), length(d + (c + b * t.y) * t.y));
+}
+
+// Distance from `position` to the curves of the bands of one direction within
+// `range` of it, in em, `result` when no curve is closer.
+fn band_distance(horizontal: bool, glyph_data: GlyphData, position: vec2<f32>, range: f32, result: f32) -> f32 {
+    var x_axis: u32;
+    var y_axis: u32;
+    var band_index_start: u32;
+    var band_scale: f32;
+    if horizontal {
+        x_axis = 0u;
+        y_axis = 1u;
+        band_index_start = glyph_data.hband_index;
+        band_scale = f32(glyph_data.band_count) / glyph_data.height_in_em;
+    } else {
+        x_axis = 1u;
+        y_axis = 0u;
+        band_index_start = glyph_data.vband_index;
+        band_scale = f32(glyph_data.band_count) / glyph_data.width_in_em;
+    }
+    let last_band = f32(glyph_data.band_count - 1u);
+    let first = u32(clamp((position[y_axis] - range) * band_scale, 0.0, last_band));
+    let last = u32(clamp((position[y_axis] + range) * band_scale, 0.0, last_band));
+    var distance = result;
+    for (var band = first; band <= last; band += 1u) {
+        var band_offset: u32;
+        var total: u32;
+        if horizontal {
+            band_offset = hband_curves[band_index_start + 2u * band];
+            total = hband_curves[band_index_start + 2u * band + 1u];
+        } else {
+            band_offset = vband_curves[band_index_start + 2u * band];
+            total = vband_curves[band_index_start + 2u * band + 1u];
+        }
+        for (var x = 0u; x < total; x += 1u) {
+            var curve_index: u32;
+            if horizontal {
+                curve_index = hband_curves[band_index_start + band_offset + x];
+            } else {
+                curve_index = vband_curves[band_index_start + band_offset + x];
+            }
+            curve_index += glyph_data.curve_texel_index;
+            let point0 = font_curves[curve_index - 1u].p2;
+            let this_curve = font_curves[curve_index];
+            // curves are sorted by their largest x, or y in vertical bands,
+            // the rest are further away
+            if max(max(point0[x_axis], this_curve.p1[x_axis]), this_curve.p2[x_axis]) < position[x_axis] - distance {
+                break;
+            }
+            distance = min(distance, bezier_distance(position, point0, this_curve.p1, this_curve.p2));
+        }
+    }
+    return distance;
+}
+
+// Distance from `position` to the outline of the glyph, in em, `range` when
+// no curve is closer. Horizontal bands leave out horizontal lines and
+// vertical bands vertical ones, so the bands within reach of both are
+// searched.
+fn outline_distance(glyph_data: GlyphData, position: vec2<f32>, range: f32) -> f32 {
+    let distance = band_distance(true, glyph_data, position, range, range);
+    return band_distance(false, glyph_data, position, range, distance);
+}
+
+// Glyph with the nonzero fill rule, the one TrueType and CFF outlines use.
 @fragment
 fn fs_main(input: FragmengInput) -> @location(0) vec4<f32> {
+    return shade(input, false);
+}
+
+// Glyph with the even-odd fill rule, overlaps of contours wound the same way
+// are holes.
+@fragment
+fn fs_even_odd(input: FragmengInput) -> @location(0) vec4<f32> {
+    return shade(input, true);
+}
+
+// Subpixel glyph, first of the two passes. Scales the target down by the
+// alpha of each channel.
+@fragment
+fn fs_subpixel_mask(input: FragmengInput) -> @location(0) vec4<f32> {
+    let alpha = subpixel_alpha(input, false);
+    return vec4<f32>(alpha, (alpha.r + alpha.g + alpha.b) / 3.0);
+}
+
+@fragment
+fn fs_subpixel_mask_even_odd(input: FragmengInput) -> @location(0) vec4<f32> {
+    let alpha = subpixel_alpha(input, true);
+    return vec4<f32>(alpha, (alpha.r + alpha.g + alpha.b) / 3.0);
+}
+
+// Subpixel glyph, second of the two passes. Adds the color weighted by the
+// alpha of each channel.
+@fragment
+fn fs_subpixel_color(input: FragmengInput) -> @location(0) vec4<f32> {
+    let alpha = subpixel_alpha(input, false);
+    return vec4<f32>(target_color(input.color.rgb) * alpha, (alpha.r + alpha.g + alpha.b) / 3.0);
+}
+
+@fragment
+fn fs_subpixel_color_even_odd(input: FragmengInput) -> @location(0) vec4<f32> {
+    let alpha = subpixel_alpha(input, true);
+    return vec4<f32>(target_color(input.color.rgb) * alpha, (alpha.r + alpha.g + alpha.b) / 3.0);
+}
+
+// Alpha of the red, green and blue channels, each from the coverage at the
+// center of its stripe. The coverage is still filtered over a whole pixel,
+// which keeps the color fringes faint.
+fn subpixel_alpha(input: FragmengInput, even_odd: bool) -> vec3<f32> {
     let glyph_data = font_info[input.glyph_id];
     if glyph_data.width_in_em < 0.0 {
-        return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+        return vec3<f32>(0.0, 0.0, 0.0);
     }
-    // transform to em coordinate system
-    var winding_number: f32 = 0.0;
-    let temp_color: vec4<f32> = vec4<f32>(1.0, 1.0, 1.0, 1.0);
-    let hband_num = u32(input.position.y / glyph_data.height_in_em * f32(glyph_data.band_count));
-    let vband_num = u32(input.position.x / glyph_data.width_in_em * f32(glyph_data.band_count));
+    // a third of a pixel in em
+    let stripe = vec2<f32>(1.0 / (3.0 * input.pixels_per_em), 0.0);
+    let left = coverage(glyph_data, input.position - stripe, input.pixels_per_em, even_odd);
+    let center = coverage(glyph_data, input.position, input.pixels_per_em, even_odd);
+    let right = coverage(glyph_data, input.position + stripe, input.pixels_per_em, even_odd);
+    var alpha = vec3<f32>(coverage_to_alpha(left), coverage_to_alpha(center), coverage_to_alpha(right));
+    if color_info.subpixel_bgr > 0.5 {
+        alpha = alpha.bgr;
+    }
+    return alpha * input.color.a;
+}
 
-    winding_number = winding_number + band_process(true, input.position, input.pixels_per_em, glyph_data.hband_index, hband_num, glyph_data.curve_texel_index);
-    winding_number = winding_number + abs(band_process(false, input.position, input.pixels_per_em, glyph_data.vband_index, vband_num, glyph_data.curve_texel_index));
+// Boosts partial coverage by the contrast, then raises it to 1 / gamma.
+fn coverage_to_alpha(coverage: f32) -> f32 {
+    // pow is undefined for a zero base on some backends
+    if coverage <= 0.0 {
+        return 0.0;
+    }
+    let contrast = color_info.contrast;
+    let boosted = coverage * (contrast + 1.0) / (coverage * contrast + 1.0);
+    return pow(boosted, 1.0 / color_info.gamma);
+}
 
-    // Take the average of the horizontal and vertical results. The absolute
-	// value ensures that either winding convention works. The square root
-	// approximates gamma correction.
-    winding_number = sqrt(clamp(winding_number * 0.5, 0.0, 1.0));
+// Colors are given in sRGB, sRGB targets expect linear colors and encode them
+// on write, so the same color ends up in the target either way.
+fn target_color(color: vec3<f32>) -> vec3<f32> {
+    if color_info.srgb_target < 0.5 {
+        return color;
+    }
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+fn shade(input: FragmengInput, even_odd: bool) -> vec4<f32> {
+    let glyph_data = font_info[input.glyph_id];
+    if glyph_data.width_in_em < 0.0 {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    }
+    let fill = coverage(glyph_data, input.position, input.pixels_per_em, even_odd);
+    var alpha = coverage_to_alpha(fill);
+    // dilation and blur radius in em, outlines are dilated fills and shadows
+    // dilated fills with an edge as wide as the blur
+    let dilation = input.effect.x;
+    let blur = input.effect.y;
+    if dilation > 0.0 || blur > 0.0 {
+        let distance = outline_distance(glyph_data, input.position, dilation + blur + 1.0 / input.pixels_per_em);
+        // distance to the edge of the dilated glyph in pixels, negative inside
+        let edge_distance = (select(distance, -distance, fill > 0.5) - dilation) * input.pixels_per_em;
+        let edge_width = max(2.0 * blur * input.pixels_per_em, 1.0);
+        let effect_alpha = smoothstep(0.0, 1.0, 0.5 - edge_distance / edge_width);
+        alpha = select(max(alpha, effect_alpha), effect_alpha, blur > 0.0);
+    }
 
-    if winding_number > 0.0001 {
-        return temp_color * winding_number;
+    if alpha > 0.0001 {
+        // the pipeline blends premultiplied colors
+        let color = vec4<f32>(target_color(input.color.rgb) * input.color.a, input.color.a);
+        return color * alpha;
     } else {
         return vec4<f32>(0.0, 0.0, 0.0, 0.0);
-        // return vec4<f32>(0.5, 0.7, 0.2, 1.0);
     }
-    // return vec4<f32>(1.0, 0.7, 0.5, 1.0);
 }
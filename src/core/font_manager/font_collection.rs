@@ -0,0 +1,422 @@
+use std::path::PathBuf;
+
+use owned_ttf_parser::{name_id, AsFaceRef, FaceMut};
+use serde::{Deserialize, Serialize};
+
+/// Picks a face of a font file, font collections (.ttc) hold several.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FaceSelector {
+    Index(u32),
+    /// Full name, family and style, or family of the face, ignoring case
+    Name(String),
+}
+
+impl FaceSelector {
+    /// Reads a face index, or a face name for anything else.
+    pub fn parse(s: &str) -> Self {
+        match s.trim().parse() {
+            Ok(index) => FaceSelector::Index(index),
+            Err(_) => FaceSelector::Name(s.trim().to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for FaceSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FaceSelector::Index(index) => write!(f, "{}", index),
+            FaceSelector::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+impl Default for FaceSelector {
+    fn default() -> Self {
+        FaceSelector::Index(0)
+    }
+}
+
+/// A coordinate on an axis of a variable font, in the units of the axis,
+/// e.g. 700 on `wght` for bold.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Variation {
+    pub axis: [u8; 4],
+    pub value: f32,
+}
+
+impl Variation {
+    /// Reads `axis=value`, e.g. `wght=700`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (axis, value) = s.split_once('=')?;
+        Some(Variation {
+            axis: axis.trim().as_bytes().try_into().ok()?,
+            value: value.trim().parse().ok()?,
+        })
+    }
+
+    #[inline]
+    pub fn tag(&self) -> owned_ttf_parser::Tag {
+        owned_ttf_parser::Tag::from_bytes(&self.axis)
+    }
+
+    /// Sets the coordinate on `face`, `None` when the face has no such axis.
+    pub fn apply<F: AsFaceRef + FaceMut>(&self, face: &mut F) -> Option<()> {
+        face.as_face_ref()
+            .variation_axes()
+            .into_iter()
+            .find(|axis| axis.tag == self.tag())?;
+        face.set_variation(self.tag(), self.value)
+    }
+}
+
+impl std::fmt::Display for Variation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", String::from_utf8_lossy(&self.axis), self.value)
+    }
+}
+
+/// A font file, the face to load from it and the instance of the face for
+/// variable fonts. Instances of the same face are separate fonts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontSource {
+    pub path: PathBuf,
+    pub face: FaceSelector,
+    /// Axes left out stay at their default
+    pub variations: Vec<Variation>,
+}
+
+impl FontSource {
+    /// The first face of the font at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FontSource {
+            path: path.into(),
+            face: FaceSelector::default(),
+            variations: Vec::new(),
+        }
+    }
+
+    /// Reads an entry of the font list, `file#face` picks a face of a
+    /// collection by index or name, e.g. `NotoSansCJK.ttc#2` or
+    /// `NotoSansCJK.ttc#Noto Sans CJK JP Bold`. Axis coordinates follow an
+    /// `@`, e.g. `Inter.ttf@wght=700,slnt=-10`, malformed ones are ignored.
+    /// A `#` followed by more of the path is part of the path.
+    pub fn parse(entry: &str) -> Self {
+        let (entry, variations) = match entry.split_once('@') {
+            Some((entry, variations)) => (
+                entry,
+                variations
+                    .split(',')
+                    .filter_map(|variation| {
+                        let parsed = Variation::parse(variation);
+                        if parsed.is_none() {
+                            log::warn!("Invalid axis coordinate {:?}", variation);
+                        }
+                        parsed
+                    })
+                    .collect(),
+            ),
+            None => (entry, Vec::new()),
+        };
+        let face = entry
+            .rsplit_once('#')
+            .filter(|(_, face)| !face.contains(['/', '\\']));
+        let mut font_source = match face {
+            Some((path, face)) => FontSource {
+                face: FaceSelector::parse(face),
+                ..FontSource::new(path)
+            },
+            None => FontSource::new(entry),
+        };
+        font_source.variations = variations;
+        font_source
+    }
+}
+
+/// Names of a face of a font file.
+#[derive(Debug, Clone)]
+pub struct FaceInfo {
+    pub index: u32,
+    pub family: String,
+    pub style: String,
+    pub full_name: String,
+}
+
+impl FaceInfo {
+    fn matches(&self, name: &str) -> bool {
+        self.full_name.eq_ignore_ascii_case(name)
+            || format!("{} {}", self.family, self.style).eq_ignore_ascii_case(name)
+    }
+}
+
+/// An axis of a variable font.
+#[derive(Debug, Clone)]
+pub struct FontAxis {
+    pub axis: [u8; 4],
+    pub name: String,
+    pub min_value: f32,
+    pub default_value: f32,
+    pub max_value: f32,
+    /// Axes the font wants hidden from users
+    pub hidden: bool,
+}
+
+/// Lists the variation axes of a face, none unless it is a variable font.
+pub fn list_axes(face: &owned_ttf_parser::Face) -> Vec<FontAxis> {
+    face.variation_axes()
+        .into_iter()
+        .map(|axis| FontAxis {
+            axis: axis.tag.to_bytes(),
+            name: face
+                .names()
+                .into_iter()
+                .filter(|name| name.name_id == axis.name_id && name.is_unicode())
+                .find_map(|name| name.to_string())
+                .unwrap_or_else(|| axis.tag.to_string()),
+            min_value: axis.min_value,
+            default_value: axis.def_value,
+            max_value: axis.max_value,
+            hidden: axis.hidden,
+        })
+        .collect()
+}
+
+/// Lists the faces of a font file, a single one unless it is a collection.
+/// Faces that fail to parse are left out.
+pub fn list_faces(font_file: &[u8]) -> Vec<FaceInfo> {
+    let face_count = owned_ttf_parser::fonts_in_collection(font_file).unwrap_or(1);
+    (0..face_count)
+        .filter_map(|index| {
+            let face = owned_ttf_parser::Face::parse(font_file, index).ok()?;
+            let name = |ids: &[u16]| {
+                ids.iter()
+                    .find_map(|&id| {
+                        face.names()
+                            .into_iter()
+                            .filter(|name| name.name_id == id && name.is_unicode())
+                            .find_map(|name| name.to_string())
+                    })
+                    .unwrap_or_default()
+            };
+            Some(FaceInfo {
+                index,
+                family: name(&[name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY]),
+                style: name(&[name_id::TYPOGRAPHIC_SUBFAMILY, name_id::SUBFAMILY]),
+                full_name: name(&[name_id::FULL_NAME]),
+            })
+        })
+        .collect()
+}
+
+/// Finds the index of the face `selector` picks. A bare family name picks
+/// the first face of the family.
+pub fn find_face(font_file: &[u8], selector: &FaceSelector) -> Option<u32> {
+    match selector {
+        FaceSelector::Index(index) => {
+            let face_count = owned_ttf_parser::fonts_in_collection(font_file).unwrap_or(1);
+            (*index < face_count).then_some(*index)
+        }
+        FaceSelector::Name(name) => {
+            let faces = list_faces(font_file);
+            faces
+                .iter()
+                .find(|face| face.matches(name))
+                .or_else(|| {
+                    faces
+                        .iter()
+                        .find(|face| face.family.eq_ignore_ascii_case(name))
+                })
+                .map(|face| face.index)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::font_manager::test_font::{self, TestGlyph};
+
+    fn font(family: &str, style: &str) -> Vec<u8> {
+        let glyphs = [TestGlyph {
+            c: 'a',
+            contours: vec![test_font::rectangle([0, 0], [500, 500], false)],
+            advance: 600,
+        }];
+        test_font::named(
+            &test_font::build(800, -200, &glyphs, &[], &[]),
+            family,
+            style,
+        )
+    }
+
+    fn collection() -> Vec<u8> {
+        test_font::collection(&[
+            font("Test Sans", "Regular"),
+            font("Test Sans", "Bold"),
+            font("Test Serif", "Regular"),
+        ])
+    }
+
+    #[test]
+    fn entries_pick_faces_by_index_or_name() {
+        let entry = FontSource::parse("data/test.ttc#2");
+        assert_eq!(entry.path, PathBuf::from("data/test.ttc"));
+        assert_eq!(entry.face, FaceSelector::Index(2));
+
+        let entry = FontSource::parse("data/test.ttc#Test Sans Bold");
+        assert_eq!(entry.path, PathBuf::from("data/test.ttc"));
+        assert_eq!(entry.face, FaceSelector::Name("Test Sans Bold".to_string()));
+
+        let entry = FontSource::parse("data/test.ttc#Test Sans@wght=700");
+        assert_eq!(entry.face, FaceSelector::Name("Test Sans".to_string()));
+        assert_eq!(
+            entry.variations,
+            vec![Variation::parse("wght=700").unwrap()]
+        );
+
+        assert_eq!(
+            FontSource::parse("data/test.ttf"),
+            FontSource::new("data/test.ttf")
+        );
+    }
+
+    #[test]
+    fn hashes_inside_paths_stay_in_the_path() {
+        assert_eq!(
+            FontSource::parse("fonts#old/test.ttf"),
+            FontSource::new("fonts#old/test.ttf")
+        );
+        let entry = FontSource::parse("fonts#old/test.ttc#1");
+        assert_eq!(entry.path, PathBuf::from("fonts#old/test.ttc"));
+        assert_eq!(entry.face, FaceSelector::Index(1));
+    }
+
+    #[test]
+    fn entries_read_axis_coordinates() {
+        let entry = FontSource::parse("data/test.ttf@wght=700,wdth=75");
+        assert_eq!(entry.path, PathBuf::from("data/test.ttf"));
+        assert_eq!(
+            entry.variations,
+            [
+                Variation {
+                    axis: *b"wght",
+                    value: 700.0,
+                },
+                Variation {
+                    axis: *b"wdth",
+                    value: 75.0,
+                },
+            ]
+        );
+        // malformed coordinates are left out
+        let entry = FontSource::parse("data/test.ttf@wght=bold,wdth=75");
+        assert_eq!(entry.variations, [Variation::parse("wdth=75").unwrap()]);
+    }
+
+    #[test]
+    fn variations_read_axis_and_value() {
+        assert_eq!(
+            Variation::parse(" slnt = -10 "),
+            Some(Variation {
+                axis: *b"slnt",
+                value: -10.0,
+            })
+        );
+        assert_eq!(Variation::parse("wght=700.5").unwrap().value, 700.5);
+        // tags are four bytes
+        assert_eq!(Variation::parse("wg=700"), None);
+        assert_eq!(Variation::parse("weight=700"), None);
+        assert_eq!(Variation::parse("wght700"), None);
+        assert_eq!(Variation::parse("wght=bold"), None);
+        assert_eq!(Variation::parse("wght="), None);
+    }
+
+    #[test]
+    fn variations_apply_to_their_axis() {
+        let variable_font = test_font::variable(
+            &font("Test Sans", "Regular"),
+            &[
+                (*b"wght", 100.0, 400.0, 900.0),
+                (*b"wdth", 50.0, 100.0, 100.0),
+            ],
+        );
+        let mut face = owned_ttf_parser::OwnedFace::from_vec(variable_font, 0).unwrap();
+        assert!(Variation::parse("wght=700")
+            .unwrap()
+            .apply(&mut face)
+            .is_some());
+        assert!(Variation::parse("wdth=75")
+            .unwrap()
+            .apply(&mut face)
+            .is_some());
+        let coordinates: Vec<_> = face
+            .as_face_ref()
+            .variation_coordinates()
+            .iter()
+            .map(|coordinate| coordinate.get())
+            .collect();
+        // normalized between the default and the max or min, in 2.14 fixed point
+        assert_eq!(coordinates, [(0.6f32 * 16384.0) as i16, -8192]);
+
+        assert!(Variation::parse("opsz=12")
+            .unwrap()
+            .apply(&mut face)
+            .is_none());
+        let mut face =
+            owned_ttf_parser::OwnedFace::from_vec(font("Test Sans", "Regular"), 0).unwrap();
+        assert!(Variation::parse("wght=700")
+            .unwrap()
+            .apply(&mut face)
+            .is_none());
+    }
+
+    #[test]
+    fn face_selectors_read_indices_and_names() {
+        assert_eq!(FaceSelector::parse("2"), FaceSelector::Index(2));
+        assert_eq!(FaceSelector::parse(" 2 "), FaceSelector::Index(2));
+        assert_eq!(
+            FaceSelector::parse("Test Sans Bold"),
+            FaceSelector::Name("Test Sans Bold".to_string())
+        );
+        assert_eq!(
+            FaceSelector::parse("-1"),
+            FaceSelector::Name("-1".to_string())
+        );
+    }
+
+    #[test]
+    fn faces_of_collections_are_listed_in_order() {
+        let faces = list_faces(&collection());
+        let names: Vec<_> = faces
+            .iter()
+            .map(|face| (face.index, face.family.as_str(), face.style.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (0, "Test Sans", "Regular"),
+                (1, "Test Sans", "Bold"),
+                (2, "Test Serif", "Regular"),
+            ]
+        );
+        assert_eq!(faces[1].full_name, "Test Sans Bold");
+
+        assert_eq!(list_faces(&font("Test Sans", "Regular")).len(), 1);
+    }
+
+    #[test]
+    fn faces_are_found_by_index_or_name() {
+        let collection = collection();
+        let find = |selector: &str| find_face(&collection, &FaceSelector::parse(selector));
+        assert_eq!(find("2"), Some(2));
+        assert_eq!(find("3"), None);
+        assert_eq!(find("Test Sans Bold"), Some(1));
+        assert_eq!(find("test serif regular"), Some(2));
+        // a family alone picks its first face
+        assert_eq!(find("Test Sans"), Some(0));
+        assert_eq!(find("Test Mono"), None);
+
+        let font = font("Test Sans", "Regular");
+        assert_eq!(find_face(&font, &FaceSelector::Index(0)), Some(0));
+        assert_eq!(find_face(&font, &FaceSelector::Index(1)), None);
+    }
+}
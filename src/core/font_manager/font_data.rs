@@ -7,9 +7,9 @@ pub struct FontData {
     pub curve_texel_index: u32,
     pub hband_index: u32,
     pub vband_index: u32,
-    band_count: u32,
-    width_in_em: f32,
-    height_in_em: f32,
+    pub band_count: u32,
+    pub width_in_em: f32,
+    pub height_in_em: f32,
 }
 
 impl FontData {
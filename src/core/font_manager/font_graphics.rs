@@ -1,9 +1,149 @@
 use std::rc::Rc;
 
+use super::glyph_buffers::GlyphBuffers;
+
+/// How glyphs with overlapping or self-intersecting contours are filled, each
+/// rule has its own pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FillRule {
+    /// Filled where the contours wind around a point in any direction, the
+    /// rule of TrueType and CFF outlines
+    #[default]
+    NonZero,
+    /// Filled where an odd number of contours wind around a point
+    EvenOdd,
+}
+
+/// How the edges of glyphs are antialiased.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Antialiasing {
+    /// One coverage per pixel, right on any background and display
+    #[default]
+    Grayscale,
+    /// One coverage per color channel, sharper small text on LCD displays
+    /// with horizontal stripes. Only fills are drawn this way, outlines and
+    /// shadows stay grayscale, and the text should be on an opaque background
+    Subpixel,
+}
+
+/// Order of the color stripes within a pixel of the display, from left to
+/// right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubpixelOrder {
+    #[default]
+    Rgb,
+    Bgr,
+}
+
+/// A draw of the glyphs of a text. Without dual-source blending the color
+/// channels can't have alphas of their own in one draw, subpixel glyphs are
+/// drawn twice, first scaling the target down by the coverage of each channel
+/// and then adding the color on top. Glyphs sharing pixels would have their
+/// colors added without masking each other, they go in separate pairs of
+/// draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlyphPass {
+    Grayscale,
+    SubpixelMask,
+    SubpixelColor,
+}
+
+impl GlyphPass {
+    pub const ALL: [GlyphPass; 3] = [
+        GlyphPass::Grayscale,
+        GlyphPass::SubpixelMask,
+        GlyphPass::SubpixelColor,
+    ];
+
+    pub fn entry_point(&self, fill_rule: FillRule) -> &'static str {
+        match (self, fill_rule) {
+            (GlyphPass::Grayscale, FillRule::NonZero) => "fs_main",
+            (GlyphPass::Grayscale, FillRule::EvenOdd) => "fs_even_odd",
+            (GlyphPass::SubpixelMask, FillRule::NonZero) => "fs_subpixel_mask",
+            (GlyphPass::SubpixelMask, FillRule::EvenOdd) => "fs_subpixel_mask_even_odd",
+            (GlyphPass::SubpixelColor, FillRule::NonZero) => "fs_subpixel_color",
+            (GlyphPass::SubpixelColor, FillRule::EvenOdd) => "fs_subpixel_color_even_odd",
+        }
+    }
+
+    pub fn blend(&self) -> wgpu::BlendState {
+        match self {
+            GlyphPass::Grayscale => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            // target * (1 - coverage of the channel)
+            GlyphPass::SubpixelMask => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            // target + color * coverage of the channel
+            GlyphPass::SubpixelColor => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+        }
+    }
+}
+
+/// Maps the coverage of a pixel to the alpha the glyph is blended with, the
+/// same on the GPU and in `font_rasterizer`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoverageCurve {
+    /// Coverage is raised to `1 / gamma`, 1 keeps the exact coverage and the
+    /// default of 2 lifts partial coverage so strokes don't look thin
+    pub gamma: f32,
+    /// Boosts partial coverage before the gamma without clipping, 0 leaves it
+    /// as is
+    pub contrast: f32,
+}
+
+impl Default for CoverageCurve {
+    fn default() -> Self {
+        CoverageCurve {
+            gamma: 2.0,
+            contrast: 0.0,
+        }
+    }
+}
+
+impl CoverageCurve {
+    /// `coverage_to_alpha` of the shader.
+    pub fn apply(&self, coverage: f32) -> f32 {
+        let coverage = coverage * (self.contrast + 1.0) / (coverage * self.contrast + 1.0);
+        coverage.powf(1.0 / self.gamma)
+    }
+}
+
 pub struct FontGraphics {
     pub vertex_buffer: Rc<wgpu::Buffer>,
     pub index_buffer: Rc<wgpu::Buffer>,
-    pub render_pipeline: Rc<wgpu::RenderPipeline>,
+    /// One per fill rule and pass, see `FontGraphics::pipeline`
+    pub render_pipelines: Vec<Rc<wgpu::RenderPipeline>>,
+    /// Window size in physical pixels, rewritten on resize
+    pub window_info_buffer: Rc<wgpu::Buffer>,
+    /// Coverage curve, whether the target is sRGB and the subpixel order
+    pub color_info_buffer: Rc<wgpu::Buffer>,
     pub uniform_bindgroup: Rc<wgpu::BindGroup>,
-    pub font_data_bindgroup: Rc<wgpu::BindGroup>,
+    /// Glyphs uploaded so far, see `FontManager::upload_glyphs`
+    pub glyph_buffers: GlyphBuffers,
+}
+
+impl FontGraphics {
+    pub fn pipeline(&self, fill_rule: FillRule, pass: GlyphPass) -> Rc<wgpu::RenderPipeline> {
+        self.render_pipelines[fill_rule as usize * GlyphPass::ALL.len() + pass as usize].clone()
+    }
 }
@@ -1,11 +1,516 @@
+use std::path::{Path, PathBuf};
+
 use serde::{Deserialize, Serialize};
 
-use super::font_data::FontData;
+use super::{font_collection::Variation, font_data::FontData};
+
+/// Version of the preprocessing output, bump it whenever the layout of
+/// [`FontDrawingData`] or the way it is built changes.
+pub const CACHE_FORMAT_VERSION: u32 = 2;
+
+/// Options of the glyph preprocessing, part of the cache key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PreprocessParams {
+    /// Face of the font file, for collections
+    pub face_index: u32,
+    /// Instance of variable fonts, the face handed to the preprocessing
+    /// must have these coordinates set
+    pub variations: Vec<Variation>,
+    pub min_band_count: u32,
+    pub max_band_count: u32,
+    /// Curves a band should hold before the glyph gets another band
+    pub curves_per_band: u32,
+    /// Max distance in em between a cubic curve and the quadratics replacing
+    /// it
+    pub cubic_tolerance: f32,
+}
+
+impl Default for PreprocessParams {
+    fn default() -> Self {
+        PreprocessParams {
+            face_index: 0,
+            variations: Vec::new(),
+            min_band_count: 2,
+            max_band_count: 16,
+            curves_per_band: 8,
+            cubic_tolerance: 1.0 / 1024.0,
+        }
+    }
+}
+
+/// Written in front of the cached data, a cache whose header doesn't match
+/// the font in use is ignored until `preprocess` rebuilds it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CacheHeader {
+    pub format_version: u32,
+    pub font_hash: u64,
+    pub params: PreprocessParams,
+}
+
+impl CacheHeader {
+    pub fn new(font_file: &[u8], params: PreprocessParams) -> Self {
+        CacheHeader {
+            format_version: CACHE_FORMAT_VERSION,
+            font_hash: hash_font(font_file),
+            params,
+        }
+    }
+
+    /// Whether data cached under this header can be drawn in place of data
+    /// built for `expected`, the same format, font file, face and variations.
+    /// The band and cubic options only change how glyphs are split, caches
+    /// built with any of them draw the same glyphs.
+    pub fn is_compatible(&self, expected: &CacheHeader) -> bool {
+        self.format_version == expected.format_version
+            && self.font_hash == expected.font_hash
+            && self.params.face_index == expected.params.face_index
+            && self.params.variations == expected.params.variations
+    }
+}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct FontDrawingData {
     pub font_data: Vec<FontData>,
     pub font_curves: Vec<[f32; 4]>,
     pub hor_band_list: Vec<u32>,
     pub ver_band_list: Vec<u32>,
 }
+
+/// Preprocessed data of a single glyph, its indices start at its own curves
+/// and bands.
+#[derive(Debug, Clone)]
+pub struct GlyphDrawingData {
+    pub glyph_data: FontData,
+    pub font_curves: Vec<[f32; 4]>,
+    pub hor_band_list: Vec<u32>,
+    pub ver_band_list: Vec<u32>,
+}
+
+impl FontDrawingData {
+    /// Appends a glyph after the curves and bands already here, `None` for
+    /// glyphs without outline.
+    pub fn push_glyph(&mut self, glyph: Option<GlyphDrawingData>) {
+        let Some(glyph) = glyph else {
+            self.font_data.push(FontData::empty());
+            return;
+        };
+        self.font_data.push(FontData {
+            curve_texel_index: self.font_curves.len() as u32,
+            hband_index: self.hor_band_list.len() as u32,
+            vband_index: self.ver_band_list.len() as u32,
+            ..glyph.glyph_data
+        });
+        self.font_curves.extend(glyph.font_curves);
+        self.hor_band_list.extend(glyph.hor_band_list);
+        self.ver_band_list.extend(glyph.ver_band_list);
+    }
+
+    /// Copies a glyph back out, `None` for glyphs without outline.
+    pub fn glyph(&self, glyph_id: u32) -> Option<GlyphDrawingData> {
+        let glyph_data = *self.font_data.get(glyph_id as usize)?;
+        if glyph_data.width_in_em < 0.0 {
+            return None;
+        }
+        // glyphs are stored one after another, the curves of this one end
+        // where the next outline starts
+        let curve_end = self.font_data[glyph_id as usize + 1..]
+            .iter()
+            .find(|next| next.width_in_em >= 0.0)
+            .map_or(self.font_curves.len(), |next| {
+                next.curve_texel_index as usize
+            });
+        let band_list = |list: &[u32], start: u32| {
+            let start = start as usize;
+            let header_len = 2 * glyph_data.band_count as usize;
+            let curve_count: u32 = list[start..start + header_len]
+                .iter()
+                .skip(1)
+                .step_by(2)
+                .sum();
+            list[start..start + header_len + curve_count as usize].to_vec()
+        };
+
+        Some(GlyphDrawingData {
+            glyph_data: FontData {
+                curve_texel_index: 0,
+                hband_index: 0,
+                vband_index: 0,
+                ..glyph_data
+            },
+            font_curves: self.font_curves[glyph_data.curve_texel_index as usize..curve_end]
+                .to_vec(),
+            hor_band_list: band_list(&self.hor_band_list, glyph_data.hband_index),
+            ver_band_list: band_list(&self.ver_band_list, glyph_data.vband_index),
+        })
+    }
+}
+
+/// Sizes of preprocessed glyph data.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrawingDataStats {
+    pub glyphs: usize,
+    /// Glyphs without outline, like spaces
+    pub empty_glyphs: usize,
+    /// Curve entries, including one move per contour
+    pub curves: usize,
+    pub max_curves_per_glyph: usize,
+    pub bands: usize,
+    pub max_band_count: u32,
+    /// Most curves in a single band
+    pub max_band_size: u32,
+    /// Average curves per band
+    pub mean_band_size: f32,
+    /// Size of the GPU buffers
+    pub bytes: usize,
+}
+
+impl FontDrawingData {
+    pub fn stats(&self) -> DrawingDataStats {
+        let mut stats = DrawingDataStats {
+            glyphs: self.font_data.len(),
+            curves: self.font_curves.len(),
+            bytes: std::mem::size_of_val(self.font_data.as_slice())
+                + std::mem::size_of_val(self.font_curves.as_slice())
+                + std::mem::size_of_val(self.hor_band_list.as_slice())
+                + std::mem::size_of_val(self.ver_band_list.as_slice()),
+            ..Default::default()
+        };
+        let mut band_curves = 0;
+        let mut curve_starts: Vec<u32> = Vec::new();
+        for glyph_data in self.font_data.iter() {
+            if glyph_data.width_in_em < 0.0 {
+                stats.empty_glyphs += 1;
+                continue;
+            }
+            curve_starts.push(glyph_data.curve_texel_index);
+            stats.bands += 2 * glyph_data.band_count as usize;
+            stats.max_band_count = stats.max_band_count.max(glyph_data.band_count);
+            for band in 0..glyph_data.band_count {
+                for (band_list, start) in [
+                    (&self.hor_band_list, glyph_data.hband_index),
+                    (&self.ver_band_list, glyph_data.vband_index),
+                ] {
+                    let size = band_list[(start + 2 * band + 1) as usize];
+                    stats.max_band_size = stats.max_band_size.max(size);
+                    band_curves += size as usize;
+                }
+            }
+        }
+        // glyphs store their curves one after another
+        curve_starts.push(self.font_curves.len() as u32);
+        stats.max_curves_per_glyph = curve_starts
+            .windows(2)
+            .map(|starts| (starts[1] - starts[0]) as usize)
+            .max()
+            .unwrap_or(0);
+        if stats.bands > 0 {
+            stats.mean_band_size = band_curves as f32 / stats.bands as f32;
+        }
+
+        stats
+    }
+}
+
+/// Cache file of a font instance in `data/font`, named after the font file.
+pub fn cache_path<P: AsRef<Path>>(font_path: P, params: &PreprocessParams) -> PathBuf {
+    let mut file_name = font_path
+        .as_ref()
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_lowercase();
+    // faces of a collection and instances of a variable font get a cache
+    // each
+    if params.face_index != 0 {
+        file_name.push_str(&format!("-{}", params.face_index));
+    }
+    for variation in params.variations.iter() {
+        file_name.push_str(&format!(
+            "-{}{}",
+            String::from_utf8_lossy(&variation.axis).trim(),
+            variation.value
+        ));
+    }
+    file_name.push_str(".bin");
+    Path::new("data").join("font").join(file_name)
+}
+
+/// Reads the cached data at `path` if its header matches `header`.
+pub fn load_cache<P: AsRef<Path>>(path: P, header: &CacheHeader) -> Option<FontDrawingData> {
+    read_cache(path, |cached_header| cached_header == header).map(|(_, data)| data)
+}
+
+/// Reads the cached data at `path` if it is compatible with `header`, see
+/// [`CacheHeader::is_compatible`], along with the header it was built with.
+pub fn load_compatible_cache<P: AsRef<Path>>(
+    path: P,
+    header: &CacheHeader,
+) -> Option<(CacheHeader, FontDrawingData)> {
+    read_cache(path, |cached_header| cached_header.is_compatible(header))
+}
+
+fn read_cache<P, F>(path: P, accept: F) -> Option<(CacheHeader, FontDrawingData)>
+where
+    P: AsRef<Path>,
+    F: Fn(&CacheHeader) -> bool,
+{
+    let file = std::fs::read(path).ok()?;
+    let mut reader = file.as_slice();
+    let cached_header: CacheHeader = bincode::deserialize_from(&mut reader).ok()?;
+    if !accept(&cached_header) {
+        return None;
+    }
+    let data = bincode::deserialize_from(&mut reader).ok()?;
+    Some((cached_header, data))
+}
+
+pub fn save_cache<P: AsRef<Path>>(path: P, header: &CacheHeader, data: &FontDrawingData) {
+    let mut file = bincode::serialize(header).expect("Unable to serialize cache header!");
+    file.extend(bincode::serialize(data).expect("Unable to serialize font data!"));
+    std::fs::write(path, file).expect("Unable to write font cache!");
+}
+
+/// 64-bit FNV-1a, stable across builds unlike the std hashers.
+fn hash_font(font_file: &[u8]) -> u64 {
+    font_file.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use owned_ttf_parser::Face;
+
+    use super::*;
+    use crate::core::font_manager::{
+        get_glyph_drawing_data,
+        test_font::{drawing_data, eng1},
+    };
+
+    /// Cache file in the temp directory, removed when dropped.
+    struct TempCache(PathBuf);
+
+    impl TempCache {
+        fn new(name: &str) -> Self {
+            TempCache(std::env::temp_dir().join(format!(
+                "gputext-{}-{}.bin",
+                name,
+                std::process::id()
+            )))
+        }
+    }
+
+    impl Drop for TempCache {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn cache_round_trips() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let params = PreprocessParams::default();
+        let data = drawing_data(&face, "aO|", &params);
+        let header = CacheHeader::new(&font_file, params);
+        let cache = TempCache::new("cache-round-trips");
+        save_cache(&cache.0, &header, &data);
+
+        let loaded = load_cache(&cache.0, &header).expect("matching cache was rejected");
+        assert_eq!(
+            bincode::serialize(&loaded).unwrap(),
+            bincode::serialize(&data).unwrap()
+        );
+    }
+
+    #[test]
+    fn mismatched_caches_are_rejected() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let params = PreprocessParams::default();
+        let data = drawing_data(&face, "a", &params);
+        let header = CacheHeader::new(&font_file, params.clone());
+        let cache = TempCache::new("mismatched-caches");
+        save_cache(&cache.0, &header, &data);
+
+        let mut other_font = font_file.clone();
+        *other_font.last_mut().unwrap() ^= 1;
+        let mismatches = [
+            (
+                "format version",
+                CacheHeader {
+                    format_version: CACHE_FORMAT_VERSION + 1,
+                    ..header.clone()
+                },
+            ),
+            ("font hash", CacheHeader::new(&other_font, params.clone())),
+            (
+                "preprocess params",
+                CacheHeader::new(
+                    &font_file,
+                    PreprocessParams {
+                        curves_per_band: params.curves_per_band + 1,
+                        ..params.clone()
+                    },
+                ),
+            ),
+        ];
+        for (field, other_header) in mismatches {
+            assert!(
+                load_cache(&cache.0, &other_header).is_none(),
+                "cache with another {} was loaded",
+                field
+            );
+        }
+        assert!(load_cache(&cache.0, &header).is_some());
+    }
+
+    #[test]
+    fn cache_paths_name_the_face_and_variations() {
+        let params = |face_index, variations: &[&str]| PreprocessParams {
+            face_index,
+            variations: variations
+                .iter()
+                .map(|variation| Variation::parse(variation).unwrap())
+                .collect(),
+            ..Default::default()
+        };
+        let path = |params| cache_path("data/Test.ttc", &params);
+        assert_eq!(path(params(0, &[])), Path::new("data/font/test.ttc.bin"));
+        assert_eq!(path(params(2, &[])), Path::new("data/font/test.ttc-2.bin"));
+        assert_eq!(
+            path(params(0, &["wght=700", "wdth=75"])),
+            Path::new("data/font/test.ttc-wght700-wdth75.bin")
+        );
+        assert_eq!(
+            path(params(1, &["slnt=-10.5"])),
+            Path::new("data/font/test.ttc-1-slnt-10.5.bin")
+        );
+
+        // instances of the same file don't share a cache
+        let instances = [
+            params(0, &[]),
+            params(0, &["wght=400"]),
+            params(0, &["wght=700"]),
+            params(0, &["wght=700", "wdth=75"]),
+            params(0, &["wght=700", "wdth=100"]),
+            params(1, &["wght=700"]),
+        ];
+        for (index, instance) in instances.iter().enumerate() {
+            for other in instances[index + 1..].iter() {
+                assert_ne!(
+                    path(instance.clone()),
+                    path(other.clone()),
+                    "{:?} and {:?} share a cache",
+                    instance.variations,
+                    other.variations
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn caches_with_other_band_options_are_compatible() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let params = PreprocessParams {
+            min_band_count: 4,
+            curves_per_band: 2,
+            cubic_tolerance: 1.0 / 256.0,
+            ..Default::default()
+        };
+        let data = drawing_data(&face, "a", &params);
+        let header = CacheHeader::new(&font_file, params);
+        let cache = TempCache::new("compatible-caches");
+        save_cache(&cache.0, &header, &data);
+
+        // what the game expects, the default band options
+        let expected = CacheHeader::new(&font_file, PreprocessParams::default());
+        assert!(load_cache(&cache.0, &expected).is_none());
+        let (cached_header, _) = load_compatible_cache(&cache.0, &expected).unwrap();
+        assert_eq!(cached_header, header);
+
+        let mut other_font = font_file.clone();
+        *other_font.last_mut().unwrap() ^= 1;
+        let mismatches = [
+            (
+                "format version",
+                CacheHeader {
+                    format_version: CACHE_FORMAT_VERSION + 1,
+                    ..expected.clone()
+                },
+            ),
+            (
+                "font hash",
+                CacheHeader::new(&other_font, PreprocessParams::default()),
+            ),
+            (
+                "face",
+                CacheHeader::new(
+                    &font_file,
+                    PreprocessParams {
+                        face_index: 1,
+                        ..Default::default()
+                    },
+                ),
+            ),
+            (
+                "variations",
+                CacheHeader::new(
+                    &font_file,
+                    PreprocessParams {
+                        variations: vec![Variation::parse("wght=700").unwrap()],
+                        ..Default::default()
+                    },
+                ),
+            ),
+        ];
+        for (field, other_header) in mismatches {
+            assert!(
+                load_compatible_cache(&cache.0, &other_header).is_none(),
+                "cache with another {} was loaded",
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn pushed_glyphs_read_back_unchanged() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let params = PreprocessParams::default();
+        // outlines of different sizes with glyphs without outline in between
+        let glyph_ids: Vec<u16> = "O|a g&"
+            .chars()
+            .map(|c| face.glyph_index(c).unwrap().0)
+            .collect();
+        let mut drawing_data = FontDrawingData::default();
+        for id in glyph_ids.iter() {
+            drawing_data.push_glyph(get_glyph_drawing_data(&face, *id, &params));
+        }
+        drawing_data.push_glyph(None);
+
+        for (index, id) in glyph_ids.iter().enumerate() {
+            let pushed = get_glyph_drawing_data(&face, *id, &params);
+            let read = drawing_data.glyph(index as u32);
+            match (pushed, read) {
+                (Some(pushed), Some(read)) => {
+                    assert_eq!(
+                        bytemuck::bytes_of(&read.glyph_data),
+                        bytemuck::bytes_of(&pushed.glyph_data),
+                        "glyph {}",
+                        index
+                    );
+                    assert_eq!(read.font_curves, pushed.font_curves, "glyph {}", index);
+                    assert_eq!(read.hor_band_list, pushed.hor_band_list, "glyph {}", index);
+                    assert_eq!(read.ver_band_list, pushed.ver_band_list, "glyph {}", index);
+                }
+                (None, None) => {}
+                _ => panic!("glyph {} lost or gained its outline", index),
+            }
+        }
+        assert!(drawing_data.glyph(glyph_ids.len() as u32).is_none());
+        assert!(drawing_data.glyph(glyph_ids.len() as u32 + 1).is_none());
+    }
+}
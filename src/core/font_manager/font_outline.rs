@@ -1,5 +1,9 @@
+const MAX_QUADS_PER_CUBIC: usize = 16;
+
 pub struct FontOutlineData {
     points: Vec<OutlineDrawCommand>,
+    contour_start: (f32, f32),
+    current: (f32, f32),
 }
 
 #[derive(Debug)]
@@ -18,15 +22,39 @@ pub enum OutlineDrawCommand {
 
 impl FontOutlineData {
     pub fn new() -> Self {
-        FontOutlineData { points: Vec::new() }
+        FontOutlineData {
+            points: Vec::new(),
+            contour_start: (0.0, 0.0),
+            current: (0.0, 0.0),
+        }
     }
 
     pub fn point_command_iter(&self) -> std::slice::Iter<OutlineDrawCommand> {
         self.points.iter()
     }
 
-    pub fn number_of_curves(&self) -> usize {
-        self.points.len()
+    /// Number of commands, cubic curves counting as the quadratics they are
+    /// split into. `cubic_tolerance` is in the units of the outline.
+    pub fn number_of_curves(&self, cubic_tolerance: f32) -> usize {
+        let mut current = [0.0, 0.0];
+        self.points
+            .iter()
+            .map(|command| match *command {
+                OutlineDrawCommand::MoveTo(x, y)
+                | OutlineDrawCommand::LineTo(x, y)
+                | OutlineDrawCommand::QuadTo(_, _, x, y) => {
+                    current = [x, y];
+                    1
+                }
+                OutlineDrawCommand::CurveTo(x1, y1, x2, y2, x, y) => {
+                    let quads =
+                        cubic_to_quadratics(current, [x1, y1], [x2, y2], [x, y], cubic_tolerance);
+                    current = [x, y];
+                    quads.len()
+                }
+                OutlineDrawCommand::Close => 1,
+            })
+            .sum()
     }
 
     #[allow(unused)]
@@ -38,23 +66,351 @@ impl FontOutlineData {
 impl owned_ttf_parser::OutlineBuilder for FontOutlineData {
     fn move_to(&mut self, x: f32, y: f32) {
         self.points.push(OutlineDrawCommand::MoveTo(x, y));
+        self.contour_start = (x, y);
+        self.current = (x, y);
     }
 
     fn line_to(&mut self, x: f32, y: f32) {
         self.points.push(OutlineDrawCommand::LineTo(x, y));
+        self.current = (x, y);
     }
 
     fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
         self.points.push(OutlineDrawCommand::QuadTo(x1, y1, x, y));
+        self.current = (x, y);
     }
 
     fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
         self.points
             .push(OutlineDrawCommand::CurveTo(x1, y1, x2, y2, x, y));
-        unreachable!("Should not reach!");
+        self.current = (x, y);
     }
 
     fn close(&mut self) {
+        // CFF contours are closed implicitly, the winding number needs the
+        // closing segment to be an actual curve
+        if self.current != self.contour_start {
+            let (x, y) = self.contour_start;
+            self.line_to(x, y);
+        }
         self.points.push(OutlineDrawCommand::Close);
     }
 }
+
+/// Approximates the cubic curve `p0, p1, p2, p3` with at most
+/// `MAX_QUADS_PER_CUBIC` quadratic curves, each one within `tolerance` of the
+/// original unless the cap is hit. Returns `(control, end)` pairs, the start
+/// of each quadratic being the end of the previous one (or `p0`). The control
+/// points lie on the tangents of the cubic, so consecutive quadratics join
+/// without a kink.
+pub fn cubic_to_quadratics(
+    p0: [f32; 2],
+    p1: [f32; 2],
+    p2: [f32; 2],
+    p3: [f32; 2],
+    tolerance: f32,
+) -> Vec<([f32; 2], [f32; 2])> {
+    let cubic = [p0, p1, p2, p3];
+    // a quadratic can't bend both ways, pieces end at the inflections
+    let mut bounds = vec![0.0];
+    bounds.extend(inflections(&cubic));
+    bounds.push(1.0);
+    let spans: Vec<_> = bounds
+        .windows(2)
+        .map(|span| (span[0], span[1]))
+        .filter(|(start, end)| end - start > 1e-3)
+        .collect();
+    let max_segments = MAX_QUADS_PER_CUBIC / spans.len();
+
+    // Replacing a cubic with the quadratic whose control point is
+    // (3 * (p1 + p2) - p0 - p3) / 4 gives an error of at most
+    // sqrt(3) / 36 * |p3 - 3 * p2 + 3 * p1 - p0|, and splitting the cubic into
+    // n pieces divides that error by n^3. That is where the search for the
+    // number of pieces starts.
+    let dx = p3[0] - 3.0 * p2[0] + 3.0 * p1[0] - p0[0];
+    let dy = p3[1] - 3.0 * p2[1] + 3.0 * p1[1] - p0[1];
+    let error = (dx * dx + dy * dy).sqrt() * 3.0f32.sqrt() / 36.0;
+
+    let mut quads = Vec::new();
+    for (index, &span) in spans.iter().enumerate() {
+        let span_error = error * (span.1 - span.0).powi(3);
+        let first_guess = ((span_error / tolerance).cbrt().ceil() as usize).clamp(1, max_segments);
+        let start = quads.last().map_or(p0, |&(_, end)| end);
+        let mut span_quads = (first_guess..max_segments)
+            .find_map(|segments| split_cubic(&cubic, span, start, segments, tolerance))
+            .unwrap_or_else(|| {
+                split_cubic(&cubic, span, start, max_segments, f32::INFINITY)
+                    .expect("Unable to split cubic curve!")
+            });
+        if index + 1 == spans.len() {
+            // end exactly where the cubic does, not on a rounded point
+            span_quads.last_mut().unwrap().1 = p3;
+        }
+        quads.extend(span_quads);
+    }
+
+    quads
+}
+
+/// Parameters in `0..1` where the cubic changes the direction it bends, in
+/// order.
+fn inflections(cubic: &[[f32; 2]; 4]) -> Vec<f32> {
+    let [p0, p1, p2, p3] = *cubic;
+    let cross = |u: [f32; 2], v: [f32; 2]| u[0] * v[1] - u[1] * v[0];
+    // the derivatives are 3 * (a + 2 * b * t + c * t^2) and 6 * (b + c * t),
+    // they are parallel where their cross product is zero
+    let a = [p1[0] - p0[0], p1[1] - p0[1]];
+    let b = [p2[0] - 2.0 * p1[0] + p0[0], p2[1] - 2.0 * p1[1] + p0[1]];
+    let c = [
+        p3[0] - 3.0 * p2[0] + 3.0 * p1[0] - p0[0],
+        p3[1] - 3.0 * p2[1] + 3.0 * p1[1] - p0[1],
+    ];
+    let (qa, qb, qc) = (cross(b, c), cross(a, c), cross(a, b));
+    let mut roots = if qa.abs() < 1e-9 * (qb.abs() + qc.abs()).max(1e-20) {
+        if qb == 0.0 {
+            Vec::new()
+        } else {
+            vec![-qc / qb]
+        }
+    } else {
+        let discriminant = qb * qb - 4.0 * qa * qc;
+        if discriminant < 0.0 {
+            Vec::new()
+        } else {
+            let root = discriminant.sqrt();
+            vec![(-qb - root) / (2.0 * qa), (-qb + root) / (2.0 * qa)]
+        }
+    };
+    roots.retain(|t| *t > 0.0 && *t < 1.0);
+    roots.sort_by(|t0, t1| t0.partial_cmp(t1).unwrap());
+    roots
+}
+
+/// Splits the cubic between the parameters `span` into `segments`
+/// quadratics from `start`, `None` when one of them is further than
+/// `tolerance` from its piece of the cubic.
+fn split_cubic(
+    cubic: &[[f32; 2]; 4],
+    span: (f32, f32),
+    start: [f32; 2],
+    segments: usize,
+    tolerance: f32,
+) -> Option<Vec<([f32; 2], [f32; 2])>> {
+    let [p0, p1, p2, p3] = *cubic;
+    let point_at = |t: f32| {
+        let mt = 1.0 - t;
+        let a = mt * mt * mt;
+        let b = 3.0 * mt * mt * t;
+        let c = 3.0 * mt * t * t;
+        let d = t * t * t;
+        [
+            a * p0[0] + b * p1[0] + c * p2[0] + d * p3[0],
+            a * p0[1] + b * p1[1] + c * p2[1] + d * p3[1],
+        ]
+    };
+    let derivative_at = |t: f32| {
+        let mt = 1.0 - t;
+        let a = 3.0 * mt * mt;
+        let b = 6.0 * mt * t;
+        let c = 3.0 * t * t;
+        [
+            a * (p1[0] - p0[0]) + b * (p2[0] - p1[0]) + c * (p3[0] - p2[0]),
+            a * (p1[1] - p0[1]) + b * (p2[1] - p1[1]) + c * (p3[1] - p2[1]),
+        ]
+    };
+
+    let mut quads = Vec::with_capacity(segments);
+    let mut start = start;
+    for index in 0..segments {
+        let t0 = span.0 + (span.1 - span.0) * index as f32 / segments as f32;
+        let t1 = span.0 + (span.1 - span.0) * (index + 1) as f32 / segments as f32;
+        let end = point_at(t1);
+        let d0 = derivative_at(t0);
+        let d1 = derivative_at(t1);
+        let control = tangent_intersection(start, d0, end, d1).unwrap_or_else(|| {
+            // degenerate tangents, like at cusps, fall back to the control
+            // point of the error bound above
+            let scale = (t1 - t0) / 3.0;
+            let c1 = [start[0] + d0[0] * scale, start[1] + d0[1] * scale];
+            let c2 = [end[0] - d1[0] * scale, end[1] - d1[1] * scale];
+            [
+                (3.0 * (c1[0] + c2[0]) - start[0] - end[0]) / 4.0,
+                (3.0 * (c1[1] + c2[1]) - start[1] - end[1]) / 4.0,
+            ]
+        });
+        // distance from points of the quadratic to a polyline following the
+        // piece of the cubic
+        let polyline: Vec<_> = (0..=32)
+            .map(|sample| point_at(t0 + (t1 - t0) * sample as f32 / 32.0))
+            .collect();
+        for sample in 1..16 {
+            let point = quadratic_point(start, control, end, sample as f32 / 16.0);
+            if distance_to_polyline(point, &polyline) > tolerance {
+                return None;
+            }
+        }
+        quads.push((control, end));
+        start = end;
+    }
+
+    Some(quads)
+}
+
+/// Where the tangent leaving `start` in direction `d0` meets the tangent
+/// arriving at `end` in direction `d1`, `None` when they are parallel or meet
+/// behind either point.
+fn tangent_intersection(
+    start: [f32; 2],
+    d0: [f32; 2],
+    end: [f32; 2],
+    d1: [f32; 2],
+) -> Option<[f32; 2]> {
+    let cross = d0[0] * d1[1] - d0[1] * d1[0];
+    let length = (d0[0].hypot(d0[1])) * (d1[0].hypot(d1[1]));
+    if length == 0.0 || cross.abs() <= length * 1e-4 {
+        return None;
+    }
+    let delta = [end[0] - start[0], end[1] - start[1]];
+    let s = (delta[0] * d1[1] - delta[1] * d1[0]) / cross;
+    let r = (d0[0] * delta[1] - d0[1] * delta[0]) / cross;
+    (s >= 0.0 && r >= 0.0).then(|| [start[0] + d0[0] * s, start[1] + d0[1] * s])
+}
+
+fn distance_to_polyline(point: [f32; 2], polyline: &[[f32; 2]]) -> f32 {
+    polyline
+        .windows(2)
+        .map(|segment| {
+            let [a, b] = [segment[0], segment[1]];
+            let ab = [b[0] - a[0], b[1] - a[1]];
+            let ap = [point[0] - a[0], point[1] - a[1]];
+            let length = ab[0] * ab[0] + ab[1] * ab[1];
+            let t = if length > 0.0 {
+                ((ap[0] * ab[0] + ap[1] * ab[1]) / length).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            (ap[0] - ab[0] * t).hypot(ap[1] - ab[1] * t)
+        })
+        .fold(f32::INFINITY, f32::min)
+}
+
+fn quadratic_point(p0: [f32; 2], p1: [f32; 2], p2: [f32; 2], t: f32) -> [f32; 2] {
+    let mt = 1.0 - t;
+    [
+        mt * mt * p0[0] + 2.0 * mt * t * p1[0] + t * t * p2[0],
+        mt * mt * p0[1] + 2.0 * mt * t * p1[1] + t * t * p2[1],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use owned_ttf_parser::OutlineBuilder;
+
+    use super::*;
+
+    // an S-shaped cubic, 1000 units wide like the glyphs of a font
+    const CUBIC: [[f32; 2]; 4] = [[0.0, 0.0], [300.0, 800.0], [700.0, -200.0], [1000.0, 600.0]];
+
+    fn cubic_point(t: f32) -> [f32; 2] {
+        let [p0, p1, p2, p3] = CUBIC;
+        let mt = 1.0 - t;
+        let weights = [mt * mt * mt, 3.0 * mt * mt * t, 3.0 * mt * t * t, t * t * t];
+        [
+            weights[0] * p0[0] + weights[1] * p1[0] + weights[2] * p2[0] + weights[3] * p3[0],
+            weights[0] * p0[1] + weights[1] * p1[1] + weights[2] * p2[1] + weights[3] * p3[1],
+        ]
+    }
+
+    fn split(tolerance: f32) -> Vec<([f32; 2], [f32; 2])> {
+        let [p0, p1, p2, p3] = CUBIC;
+        cubic_to_quadratics(p0, p1, p2, p3, tolerance)
+    }
+
+    #[test]
+    fn quadratics_join_the_endpoints() {
+        let quads = split(1.0);
+        assert_eq!(quads.last().unwrap().1, CUBIC[3]);
+        // every piece ends on the cubic
+        let cubic: Vec<_> = (0..=4096).map(|t| cubic_point(t as f32 / 4096.0)).collect();
+        for (_, end) in quads.iter() {
+            assert!(distance_to_polyline(*end, &cubic) < 1e-2);
+        }
+    }
+
+    #[test]
+    fn quadratics_join_without_kinks() {
+        let quads = split(1.0);
+        let cross = |a: [f32; 2], b: [f32; 2]| {
+            (a[0] * b[1] - a[1] * b[0]) / (a[0].hypot(a[1]) * b[0].hypot(b[1]))
+        };
+        let direction = |from: [f32; 2], to: [f32; 2]| [to[0] - from[0], to[1] - from[1]];
+        // leaves p0 along the tangent of the cubic
+        assert!(
+            cross(
+                direction(CUBIC[0], quads[0].0),
+                direction(CUBIC[0], CUBIC[1])
+            )
+            .abs()
+                < 1e-3
+        );
+        for pair in quads.windows(2) {
+            let (control, joint) = pair[0];
+            let (next_control, _) = pair[1];
+            let incoming = direction(control, joint);
+            let outgoing = direction(joint, next_control);
+            assert!(cross(incoming, outgoing).abs() < 1e-3);
+            assert!(incoming[0] * outgoing[0] + incoming[1] * outgoing[1] > 0.0);
+        }
+    }
+
+    #[test]
+    fn quadratics_stay_within_the_tolerance() {
+        let cubic: Vec<_> = (0..=4096).map(|t| cubic_point(t as f32 / 4096.0)).collect();
+        for tolerance in [4.0, 1.0, 0.25] {
+            let quads = split(tolerance);
+            assert!(quads.len() < MAX_QUADS_PER_CUBIC);
+            let mut start = CUBIC[0];
+            let mut quad_points = Vec::new();
+            for &(control, end) in quads.iter() {
+                quad_points.extend(
+                    (0..=64).map(|u| quadratic_point(start, control, end, u as f32 / 64.0)),
+                );
+                start = end;
+            }
+            // both ways, no part of either curve is far from the other
+            for &point in quad_points.iter() {
+                let error = distance_to_polyline(point, &cubic);
+                assert!(error <= tolerance * 1.1, "{} > {}", error, tolerance);
+            }
+            for &point in cubic.iter().step_by(16) {
+                let error = distance_to_polyline(point, &quad_points);
+                assert!(error <= tolerance * 1.1, "{} > {}", error, tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn quadratics_are_capped() {
+        let quads = split(1e-6);
+        assert_eq!(quads.len(), MAX_QUADS_PER_CUBIC);
+        assert_eq!(quads.last().unwrap().1, CUBIC[3]);
+    }
+
+    #[test]
+    fn cubics_count_as_their_quadratics() {
+        let mut outline = FontOutlineData::new();
+        outline.move_to(CUBIC[0][0], CUBIC[0][1]);
+        outline.curve_to(
+            CUBIC[1][0],
+            CUBIC[1][1],
+            CUBIC[2][0],
+            CUBIC[2][1],
+            CUBIC[3][0],
+            CUBIC[3][1],
+        );
+        outline.close();
+        // move, quadratics, closing line and close
+        assert_eq!(outline.number_of_curves(1.0), split(1.0).len() + 3);
+        assert!(split(1.0).len() > 1);
+    }
+}
@@ -0,0 +1,678 @@
+//! CPU port of the coverage computation in `draw_shader.wgsl`.
+//!
+//! Reads the same `FontDrawingData` the GPU does and follows `band_process`,
+//! `outline_distance` and `fs_main` step by step, including the early exit
+//! on sorted bands, so its output can be diffed against a frame rendered by
+//! the shader. Keep both in sync when changing either one.
+
+use super::{
+    font_data::FontData,
+    font_graphics::{CoverageCurve, FillRule, SubpixelOrder},
+    font_info_saving::FontDrawingData,
+};
+
+pub struct GlyphBitmap {
+    pub width: usize,
+    pub height: usize,
+    /// Pixels from the left of the glyph's bounding box to the left of the
+    /// bitmap, negative as the bitmap holds the fringe around the box
+    pub left: i32,
+    /// Pixels from the bottom of the glyph's bounding box to the bottom of
+    /// the bitmap, negative like `left`
+    pub bottom: i32,
+    /// Coverage in `0.0..=1.0`, row by row from the top of the glyph
+    pub coverage: Vec<f32>,
+}
+
+impl GlyphBitmap {
+    #[inline]
+    pub fn get(&self, x: usize, y: usize) -> f32 {
+        self.coverage[y * self.width + x]
+    }
+
+    pub fn rows(&self) -> std::slice::Chunks<'_, f32> {
+        self.coverage.chunks(self.width.max(1))
+    }
+}
+
+/// Rasterizes `glyph_id` at `pixels_per_em`, with the bottom left corner of
+/// its bounding box on a pixel corner. The bitmap spans the quad `vs_main`
+/// draws, the bounding box grown by half a pixel on every side. Returns
+/// `None` for glyphs without outline.
+pub fn rasterize_glyph(
+    drawing_data: &FontDrawingData,
+    glyph_id: u32,
+    pixels_per_em: f32,
+    fill_rule: FillRule,
+    coverage_curve: CoverageCurve,
+) -> Option<GlyphBitmap> {
+    let glyph_data = drawing_data.font_data.get(glyph_id as usize)?;
+    if glyph_data.width_in_em < 0.0 {
+        return None;
+    }
+    // vs_main grows the quad by half a pixel, the bitmap by whole pixels
+    let margin = 0.5 / pixels_per_em;
+    let border = 1;
+    let width = (glyph_data.width_in_em * pixels_per_em).ceil() as usize + 2 * border;
+    let height = (glyph_data.height_in_em * pixels_per_em).ceil() as usize + 2 * border;
+    let mut coverage = Vec::with_capacity(width * height);
+    for row in 0..height {
+        for column in 0..width {
+            // pixel center in em, relative to the bottom left of the glyph
+            let x = (column as f32 - border as f32 + 0.5) / pixels_per_em;
+            let y = ((height - row) as f32 - border as f32 - 0.5) / pixels_per_em;
+            if x <= -margin
+                || y <= -margin
+                || x >= glyph_data.width_in_em + margin
+                || y >= glyph_data.height_in_em + margin
+            {
+                // outside of the quad drawn by vs_main, no fragment there
+                coverage.push(0.0);
+                continue;
+            }
+            coverage.push(pixel_coverage(
+                drawing_data,
+                glyph_data,
+                [x, y],
+                pixels_per_em,
+                fill_rule,
+                coverage_curve,
+            ));
+        }
+    }
+
+    Some(GlyphBitmap {
+        width,
+        height,
+        left: -(border as i32),
+        bottom: -(border as i32),
+        coverage,
+    })
+}
+
+/// Same as `fs_main` and `fs_even_odd`, `position` is in em relative to the
+/// bottom left of the glyph's bounding box.
+pub fn pixel_coverage(
+    drawing_data: &FontDrawingData,
+    glyph_data: &FontData,
+    position: [f32; 2],
+    pixels_per_em: f32,
+    fill_rule: FillRule,
+    coverage_curve: CoverageCurve,
+) -> f32 {
+    // positions past the bounding box use the edge bands
+    let last_band = (glyph_data.band_count - 1) as f32;
+    let hband_num = (position[1] / glyph_data.height_in_em * glyph_data.band_count as f32)
+        .clamp(0.0, last_band) as u32;
+    let vband_num = (position[0] / glyph_data.width_in_em * glyph_data.band_count as f32)
+        .clamp(0.0, last_band) as u32;
+
+    let horizontal = band_process(
+        true,
+        drawing_data,
+        position,
+        pixels_per_em,
+        glyph_data.hband_index,
+        hband_num,
+        glyph_data.curve_texel_index,
+    );
+    let vertical = band_process(
+        false,
+        drawing_data,
+        position,
+        pixels_per_em,
+        glyph_data.vband_index,
+        vband_num,
+        glyph_data.curve_texel_index,
+    );
+    let coverage = coverage_curve.apply(
+        (apply_fill_rule(horizontal, fill_rule) + apply_fill_rule(vertical, fill_rule)) * 0.5,
+    );
+
+    if coverage > 0.0001 {
+        coverage
+    } else {
+        0.0
+    }
+}
+
+/// Same as `subpixel_alpha` of the shader before the text's alpha, the alphas
+/// of the red, green and blue channels.
+pub fn subpixel_coverage(
+    drawing_data: &FontDrawingData,
+    glyph_data: &FontData,
+    position: [f32; 2],
+    pixels_per_em: f32,
+    fill_rule: FillRule,
+    coverage_curve: CoverageCurve,
+    subpixel_order: SubpixelOrder,
+) -> [f32; 3] {
+    let stripe = 1.0 / (3.0 * pixels_per_em);
+    let [left, center, right] = [-stripe, 0.0, stripe].map(|offset| {
+        pixel_coverage(
+            drawing_data,
+            glyph_data,
+            [position[0] + offset, position[1]],
+            pixels_per_em,
+            fill_rule,
+            coverage_curve,
+        )
+    });
+    match subpixel_order {
+        SubpixelOrder::Rgb => [left, center, right],
+        SubpixelOrder::Bgr => [right, center, left],
+    }
+}
+
+// `apply_fill_rule` of the shader
+fn apply_fill_rule(winding_number: f32, fill_rule: FillRule) -> f32 {
+    let winding = winding_number.abs();
+    match fill_rule {
+        FillRule::NonZero => winding.min(1.0),
+        FillRule::EvenOdd => 1.0 - (1.0 - winding % 2.0).abs(),
+    }
+}
+
+fn band_process(
+    horizontal: bool,
+    drawing_data: &FontDrawingData,
+    pixel: [f32; 2],
+    pixels_per_em: f32,
+    band_index_start: u32,
+    band_num: u32,
+    curve_index_start: u32,
+) -> f32 {
+    let (x_axis, y_axis, band_curves) = if horizontal {
+        (0, 1, &drawing_data.hor_band_list)
+    } else {
+        (1, 0, &drawing_data.ver_band_list)
+    };
+    let band_index_start = band_index_start as usize;
+    let band_header = band_index_start + 2 * band_num as usize;
+    let band_offset = band_curves[band_header] as usize;
+    let total = band_curves[band_header + 1] as usize;
+
+    let mut winding_number = 0.0;
+    for x in 0..total {
+        let curve_index =
+            (band_curves[band_index_start + band_offset + x] + curve_index_start) as usize;
+        let previous_curve = drawing_data.font_curves[curve_index - 1];
+        let this_curve = drawing_data.font_curves[curve_index];
+        let point0 = [previous_curve[2] - pixel[0], previous_curve[3] - pixel[1]];
+        let point1 = [this_curve[0] - pixel[0], this_curve[1] - pixel[1]];
+        let point2 = [this_curve[2] - pixel[0], this_curve[3] - pixel[1]];
+        let max_x = point0[x_axis].max(point1[x_axis]).max(point2[x_axis]);
+        if max_x * pixels_per_em < -0.5 {
+            // curves are sorted by descending max x, the rest are all behind
+            break;
+        }
+
+        let mut shift_num = 0;
+        shift_num += step(point0[y_axis]) * 2;
+        shift_num += step(point1[y_axis]) * 4;
+        shift_num += step(point2[y_axis]) * 8;
+
+        let res = (0x2e74u32 >> shift_num) & 3;
+        if res == 0 {
+            continue;
+        }
+        // solve the equation: a*t*t - 2*b*t + c = 0
+        let a = [
+            point0[0] - 2.0 * point1[0] + point2[0],
+            point0[1] - 2.0 * point1[1] + point2[1],
+        ];
+        let b = [point0[0] - point1[0], point0[1] - point1[1]];
+        let c = point0;
+        let d = (b[y_axis] * b[y_axis] - a[y_axis] * c[y_axis])
+            .max(0.0)
+            .sqrt();
+        let ay = 1.0 / a[y_axis];
+        let (t1, t2) = if a[y_axis].abs() < 0.0001 {
+            // its a line, not a curve
+            let t = c[y_axis] / (2.0 * b[y_axis]);
+            (t, t)
+        } else {
+            ((b[y_axis] - d) * ay, (b[y_axis] + d) * ay)
+        };
+
+        if (res & 0x01) > 0 {
+            let x1 = (a[x_axis] * t1 - 2.0 * b[x_axis]) * t1 + c[x_axis];
+            winding_number += (pixels_per_em * x1 + 0.5).clamp(0.0, 1.0);
+        }
+
+        if res > 1 {
+            let x2 = (a[x_axis] * t2 - 2.0 * b[x_axis]) * t2 + c[x_axis];
+            winding_number -= (pixels_per_em * x2 + 0.5).clamp(0.0, 1.0);
+        }
+    }
+
+    winding_number
+}
+
+/// Same as `outline_distance` of the shader, the distance in em from
+/// `position` to the outline of the glyph, `range` when no curve is closer.
+pub fn outline_distance(
+    drawing_data: &FontDrawingData,
+    glyph_data: &FontData,
+    position: [f32; 2],
+    range: f32,
+) -> f32 {
+    // horizontal bands leave out horizontal lines and vertical bands
+    // vertical ones
+    let distance = band_distance(true, drawing_data, glyph_data, position, range, range);
+    band_distance(false, drawing_data, glyph_data, position, range, distance)
+}
+
+fn band_distance(
+    horizontal: bool,
+    drawing_data: &FontDrawingData,
+    glyph_data: &FontData,
+    position: [f32; 2],
+    range: f32,
+    result: f32,
+) -> f32 {
+    let (x_axis, y_axis, band_curves, band_index_start, extent) = if horizontal {
+        (
+            0,
+            1,
+            &drawing_data.hor_band_list,
+            glyph_data.hband_index as usize,
+            glyph_data.height_in_em,
+        )
+    } else {
+        (
+            1,
+            0,
+            &drawing_data.ver_band_list,
+            glyph_data.vband_index as usize,
+            glyph_data.width_in_em,
+        )
+    };
+    let band_scale = glyph_data.band_count as f32 / extent;
+    let last_band = (glyph_data.band_count - 1) as f32;
+    let first = ((position[y_axis] - range) * band_scale).clamp(0.0, last_band) as usize;
+    let last = ((position[y_axis] + range) * band_scale).clamp(0.0, last_band) as usize;
+
+    let mut distance = result;
+    for band in first..=last {
+        let band_offset = band_curves[band_index_start + 2 * band] as usize;
+        let total = band_curves[band_index_start + 2 * band + 1] as usize;
+        for x in 0..total {
+            let curve_index = (band_curves[band_index_start + band_offset + x]
+                + glyph_data.curve_texel_index) as usize;
+            let previous_curve = drawing_data.font_curves[curve_index - 1];
+            let this_curve = drawing_data.font_curves[curve_index];
+            let point0 = [previous_curve[2], previous_curve[3]];
+            let point1 = [this_curve[0], this_curve[1]];
+            let point2 = [this_curve[2], this_curve[3]];
+            // curves are sorted by their largest x, or y in vertical bands,
+            // the rest are further away
+            if point0[x_axis].max(point1[x_axis]).max(point2[x_axis]) < position[x_axis] - distance
+            {
+                break;
+            }
+            distance = distance.min(bezier_distance(position, point0, point1, point2));
+        }
+    }
+
+    distance
+}
+
+// `bezier_distance` of the shader
+fn bezier_distance(position: [f32; 2], p0: [f32; 2], p1: [f32; 2], p2: [f32; 2]) -> f32 {
+    let dot = |u: [f32; 2], v: [f32; 2]| u[0] * v[0] + u[1] * v[1];
+    let length = |u: [f32; 2]| dot(u, u).sqrt();
+    let a = [p1[0] - p0[0], p1[1] - p0[1]];
+    let b = [p0[0] - 2.0 * p1[0] + p2[0], p0[1] - 2.0 * p1[1] + p2[1]];
+    let d = [p0[0] - position[0], p0[1] - position[1]];
+    // point of the curve at `t`, relative to `position`
+    let at = |t: f32| {
+        [
+            d[0] + (2.0 * a[0] + b[0] * t) * t,
+            d[1] + (2.0 * a[1] + b[1] * t) * t,
+        ]
+    };
+    if dot(b, b) < 1e-10 {
+        // a line
+        let line = [p2[0] - p0[0], p2[1] - p0[1]];
+        let t = (-dot(d, line) / dot(line, line).max(1e-10)).clamp(0.0, 1.0);
+        return length([d[0] + line[0] * t, d[1] + line[1] * t]);
+    }
+    let kk = 1.0 / dot(b, b);
+    let kx = kk * dot(a, b);
+    let ky = kk * (2.0 * dot(a, a) + dot(d, b)) / 3.0;
+    let kz = kk * dot(d, a);
+    let p = ky - kx * kx;
+    let q = kx * (2.0 * kx * kx - 3.0 * ky) + kz;
+    let h = q * q + 4.0 * p * p * p;
+    if h >= 0.0 {
+        let root = |x: f32| x.signum() * x.abs().powf(1.0 / 3.0);
+        let u = root((h.sqrt() - q) * 0.5);
+        let v = root((-h.sqrt() - q) * 0.5);
+        let t = (u + v - kx).clamp(0.0, 1.0);
+        return length(at(t));
+    }
+    let z = (-p).sqrt();
+    let angle = (q / (p * z * 2.0)).acos() / 3.0;
+    let m = angle.cos();
+    let n = angle.sin() * 1.7320508;
+    let t1 = ((m + m) * z - kx).clamp(0.0, 1.0);
+    let t2 = ((-n - m) * z - kx).clamp(0.0, 1.0);
+    length(at(t1)).min(length(at(t2)))
+}
+
+// WGSL `step(0.0, value)`
+#[inline]
+fn step(value: f32) -> u32 {
+    if value >= 0.0 {
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::font_manager::test_font::{self, eng1, glyph_of, TestGlyph};
+
+    /// Drawing data holding only the glyph of `c` from `data/eng1.ttf`, and
+    /// the id of the glyph.
+    fn eng1_glyph(c: char) -> (FontDrawingData, u32) {
+        glyph_of(&eng1(), c)
+    }
+
+    /// Coverage without gamma at `position` in pixels from the bottom left
+    /// of the glyph's bounding box.
+    fn coverage_at(c: char, position: [f32; 2], pixels_per_em: f32) -> f32 {
+        let (drawing_data, glyph_id) = eng1_glyph(c);
+        let glyph_data = &drawing_data.font_data[glyph_id as usize];
+        pixel_coverage(
+            &drawing_data,
+            glyph_data,
+            position.map(|x| x / pixels_per_em),
+            pixels_per_em,
+            FillRule::NonZero,
+            CoverageCurve {
+                gamma: 1.0,
+                contrast: 0.0,
+            },
+        )
+    }
+
+    fn size_in_pixels(c: char, pixels_per_em: f32) -> [f32; 2] {
+        let (drawing_data, glyph_id) = eng1_glyph(c);
+        let glyph_data = &drawing_data.font_data[glyph_id as usize];
+        [
+            glyph_data.width_in_em * pixels_per_em,
+            glyph_data.height_in_em * pixels_per_em,
+        ]
+    }
+
+    #[test]
+    fn stems_are_fully_covered() {
+        // the bar is a single rectangle as large as its bounding box
+        let [width, height] = size_in_pixels('|', 64.0);
+        assert!(width > 2.0);
+        assert_eq!(coverage_at('|', [width / 2.0, height / 2.0], 64.0), 1.0);
+        let [width, height] = size_in_pixels('l', 64.0);
+        assert!(coverage_at('l', [width / 2.0, height * 0.6], 64.0) > 0.999);
+    }
+
+    #[test]
+    fn outside_is_empty() {
+        let [width, height] = size_in_pixels('|', 64.0);
+        for position in [
+            [-2.0, height / 2.0],
+            [width + 2.0, height / 2.0],
+            [width / 2.0, -2.0],
+            [width / 2.0, height + 2.0],
+        ] {
+            assert_eq!(coverage_at('|', position, 64.0), 0.0, "{:?}", position);
+        }
+        // the counter of the O
+        let [width, height] = size_in_pixels('O', 64.0);
+        assert_eq!(coverage_at('O', [width / 2.0, height / 2.0], 64.0), 0.0);
+    }
+
+    #[test]
+    fn edges_are_partially_covered() {
+        let [width, height] = size_in_pixels('|', 64.0);
+        // pixels centered on the left and right edges are half inside
+        for x in [0.0, width] {
+            let coverage = coverage_at('|', [x, height / 2.0], 64.0);
+            assert!(coverage > 0.2 && coverage < 0.8, "{}", coverage);
+        }
+        // coverage falls off across the edge
+        let inside = coverage_at('|', [0.25, height / 2.0], 64.0);
+        let outside = coverage_at('|', [-0.25, height / 2.0], 64.0);
+        assert!(inside > outside);
+    }
+
+    #[test]
+    fn bitmaps_cover_the_glyph() {
+        let (drawing_data, glyph_id) = eng1_glyph('|');
+        let bitmap = rasterize_glyph(
+            &drawing_data,
+            glyph_id,
+            32.0,
+            FillRule::NonZero,
+            CoverageCurve::default(),
+        )
+        .unwrap();
+        let row = bitmap.height / 2;
+        assert!((0..bitmap.width).any(|x| bitmap.get(x, row) == 1.0));
+        let (drawing_data, glyph_id) = eng1_glyph(' ');
+        assert!(rasterize_glyph(
+            &drawing_data,
+            glyph_id,
+            32.0,
+            FillRule::NonZero,
+            CoverageCurve::default()
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn bitmaps_hold_the_fringe() {
+        let (drawing_data, glyph_id) = eng1_glyph('|');
+        let pixels_per_em = 33.0;
+        let coverage_curve = CoverageCurve {
+            gamma: 1.0,
+            contrast: 0.0,
+        };
+        let bitmap = rasterize_glyph(
+            &drawing_data,
+            glyph_id,
+            pixels_per_em,
+            FillRule::NonZero,
+            coverage_curve,
+        )
+        .unwrap();
+        let [width, height] = size_in_pixels('|', pixels_per_em);
+        assert_eq!((bitmap.left, bitmap.bottom), (-1, -1));
+        assert_eq!(bitmap.width, width.ceil() as usize + 2);
+        assert_eq!(bitmap.height, height.ceil() as usize + 2);
+        // pixels are sampled at their centers relative to the origin
+        let glyph_data = &drawing_data.font_data[glyph_id as usize];
+        let (x, y) = (2, bitmap.height / 2);
+        let center = [
+            (bitmap.left as f32 + x as f32 + 0.5) / pixels_per_em,
+            (bitmap.bottom as f32 + (bitmap.height - y) as f32 - 0.5) / pixels_per_em,
+        ];
+        assert_eq!(
+            bitmap.get(x, y),
+            pixel_coverage(
+                &drawing_data,
+                glyph_data,
+                center,
+                pixels_per_em,
+                FillRule::NonZero,
+                coverage_curve
+            )
+        );
+        // nothing drawn inside the quad is left out, a few pixels further
+        // out on every side
+        let margin = 0.5 / pixels_per_em;
+        let mut expected = 0.0;
+        for row in -4..height as i32 + 4 {
+            for column in -4..width as i32 + 4 {
+                let center = [
+                    (column as f32 + 0.5) / pixels_per_em,
+                    (row as f32 + 0.5) / pixels_per_em,
+                ];
+                if center[0] > -margin
+                    && center[1] > -margin
+                    && center[0] < glyph_data.width_in_em + margin
+                    && center[1] < glyph_data.height_in_em + margin
+                {
+                    expected += pixel_coverage(
+                        &drawing_data,
+                        glyph_data,
+                        center,
+                        pixels_per_em,
+                        FillRule::NonZero,
+                        coverage_curve,
+                    );
+                }
+            }
+        }
+        let total: f32 = bitmap.coverage.iter().sum();
+        assert!((total - expected).abs() < 1e-3, "{} {}", total, expected);
+        // the outermost pixels are past the quad
+        let last_row = bitmap.rows().last().unwrap();
+        assert!(bitmap.rows().next().unwrap().iter().all(|&c| c == 0.0));
+        assert!(last_row.iter().all(|&c| c == 0.0));
+        assert!(bitmap
+            .rows()
+            .all(|row| row[0] == 0.0 && row[bitmap.width - 1] == 0.0));
+    }
+
+    #[test]
+    fn flat_edges_are_in_reach_of_outlines() {
+        // the middles of the tops and bottoms of these are horizontal lines
+        for c in ['-', 'E', 'T'] {
+            let (drawing_data, glyph_id) = eng1_glyph(c);
+            let glyph_data = &drawing_data.font_data[glyph_id as usize];
+            let middle = glyph_data.width_in_em / 2.0;
+            for (y, expected) in [
+                (glyph_data.height_in_em + 0.02, 0.02),
+                (-0.03, 0.03),
+                (glyph_data.height_in_em + 0.5, 0.1),
+            ] {
+                let distance = outline_distance(&drawing_data, glyph_data, [middle, y], 0.1);
+                assert!(
+                    (distance - expected).abs() < 1e-4,
+                    "{:?} at {}: {}",
+                    c,
+                    y,
+                    distance
+                );
+            }
+        }
+    }
+
+    /// Coverage without gamma under both fill rules at `positions` in font
+    /// units, of glyphs of a font with 1000 units per em whose bounding
+    /// boxes start at the origin.
+    fn fill_rule_coverage(font_file: &[u8], c: char, positions: &[[f32; 2]]) -> Vec<[f32; 2]> {
+        let (drawing_data, glyph_id) = glyph_of(font_file, c);
+        let glyph_data = &drawing_data.font_data[glyph_id as usize];
+        positions
+            .iter()
+            .map(|position| {
+                [FillRule::NonZero, FillRule::EvenOdd].map(|fill_rule| {
+                    pixel_coverage(
+                        &drawing_data,
+                        glyph_data,
+                        position.map(|x| x / 1000.0),
+                        64.0,
+                        fill_rule,
+                        CoverageCurve {
+                            gamma: 1.0,
+                            contrast: 0.0,
+                        },
+                    )
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fill_rules_differ_where_contours_overlap() {
+        // two squares wound the same way, overlapping in the middle
+        let font_file = test_font::build(
+            1000,
+            0,
+            &[TestGlyph {
+                c: 'a',
+                contours: vec![
+                    test_font::rectangle([0, 0], [600, 600], false),
+                    test_font::rectangle([400, 400], [1000, 1000], false),
+                ],
+                advance: 1000,
+            }],
+            &[],
+            &[],
+        );
+        let coverage = fill_rule_coverage(
+            &font_file,
+            'a',
+            &[
+                [500.0, 500.0],
+                [200.0, 200.0],
+                [800.0, 800.0],
+                [200.0, 800.0],
+            ],
+        );
+        assert_eq!(coverage[0], [1.0, 0.0], "in the overlap");
+        assert_eq!(coverage[1], [1.0, 1.0], "in the first square");
+        assert_eq!(coverage[2], [1.0, 1.0], "in the second square");
+        assert_eq!(coverage[3], [0.0, 0.0], "outside");
+    }
+
+    #[test]
+    fn fill_rules_differ_inside_self_intersections() {
+        // a pentagram drawn in one stroke, its center is wound twice
+        let corners: Vec<[i16; 2]> = (0..5)
+            .map(|index| {
+                let angle =
+                    std::f32::consts::FRAC_PI_2 - (index * 2) as f32 * std::f32::consts::TAU / 5.0;
+                [
+                    (500.0 + 500.0 * angle.cos()).round() as i16,
+                    (500.0 + 500.0 * angle.sin()).round() as i16,
+                ]
+            })
+            .collect();
+        let min = corners.iter().fold([i16::MAX; 2], |min, corner| {
+            [min[0].min(corner[0]), min[1].min(corner[1])]
+        });
+        let font_file = test_font::build(
+            1000,
+            0,
+            &[TestGlyph {
+                c: 'a',
+                contours: vec![corners],
+                advance: 1000,
+            }],
+            &[],
+            &[],
+        );
+        // positions relative to the bounding box
+        let at = |x: f32, y: f32| [x - min[0] as f32, y - min[1] as f32];
+        let coverage = fill_rule_coverage(
+            &font_file,
+            'a',
+            &[
+                at(500.0, 500.0),
+                at(500.0, 900.0),
+                at(150.0, 600.0),
+                at(200.0, 900.0),
+            ],
+        );
+        assert_eq!(coverage[0], [1.0, 0.0], "in the center");
+        assert_eq!(coverage[1], [1.0, 1.0], "in the top point");
+        assert_eq!(coverage[2], [1.0, 1.0], "in the left point");
+        assert_eq!(coverage[3], [0.0, 0.0], "outside");
+    }
+}
@@ -0,0 +1,258 @@
+use std::rc::Rc;
+
+use wgpu::util::DeviceExt;
+
+use crate::core::graphics::{GpuContext, UpdateInfo};
+
+use super::{font_data::FontData, font_info_saving::GlyphDrawingData};
+
+// Entries the curve and band buffers start with
+const INITIAL_CAPACITY: u64 = 4096;
+
+/// Storage buffers holding the glyphs drawn so far. Glyphs are appended the
+/// first time a text needs them, the buffers grow when full and the bind
+/// group is rebuilt with them.
+pub struct GlyphBuffers {
+    layout: wgpu::BindGroupLayout,
+    /// One entry per glyph of every font, empty until the glyph is uploaded
+    font_info_buffer: Rc<wgpu::Buffer>,
+    font_glyph_offset_buffer: wgpu::Buffer,
+    font_curves: GrowableBuffer<[f32; 4]>,
+    hor_band_list: GrowableBuffer<u32>,
+    ver_band_list: GrowableBuffer<u32>,
+    bind_group: Rc<wgpu::BindGroup>,
+}
+
+/// A storage buffer only ever appended to, with a copy of its content to
+/// fill the bigger buffer replacing it.
+struct GrowableBuffer<T> {
+    label: &'static str,
+    buffer: Rc<wgpu::Buffer>,
+    content: Vec<T>,
+}
+
+impl GlyphBuffers {
+    /// `font_glyph_offsets` holds the index of the first glyph of each font,
+    /// `glyph_count` the glyphs of all fonts.
+    pub fn new(gpu_context: &GpuContext, font_glyph_offsets: &[u32], glyph_count: usize) -> Self {
+        let device = &gpu_context.device;
+        let storage_entry = |binding, visibility, element_size: usize| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(element_size as _),
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Font Data"),
+            entries: &[
+                storage_entry(
+                    0,
+                    wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    std::mem::size_of::<FontData>(),
+                ),
+                storage_entry(
+                    1,
+                    wgpu::ShaderStages::FRAGMENT,
+                    std::mem::size_of::<[f32; 4]>(),
+                ),
+                storage_entry(2, wgpu::ShaderStages::FRAGMENT, std::mem::size_of::<u32>()),
+                storage_entry(3, wgpu::ShaderStages::FRAGMENT, std::mem::size_of::<u32>()),
+                storage_entry(4, wgpu::ShaderStages::VERTEX, std::mem::size_of::<u32>()),
+            ],
+        });
+
+        let font_info_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Font Rect Buffer"),
+            contents: bytemuck::cast_slice(&vec![FontData::empty(); glyph_count.max(1)]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let font_glyph_offset_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Font Glyph Offset Buffer"),
+                contents: bytemuck::cast_slice(font_glyph_offsets),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let font_curves = GrowableBuffer::new("Glyph Curve Buffer", device);
+        let hor_band_list = GrowableBuffer::new("Glyph Curve Ordering Buffer", device);
+        let ver_band_list = GrowableBuffer::new("Glyph Curve Ordering Buffer", device);
+        let bind_group = Rc::new(create_bind_group(
+            device,
+            &layout,
+            [
+                &font_info_buffer,
+                &font_curves.buffer,
+                &hor_band_list.buffer,
+                &ver_band_list.buffer,
+                &font_glyph_offset_buffer,
+            ],
+        ));
+
+        GlyphBuffers {
+            layout,
+            font_info_buffer: Rc::new(font_info_buffer),
+            font_glyph_offset_buffer,
+            font_curves,
+            hor_band_list,
+            ver_band_list,
+            bind_group,
+        }
+    }
+
+    /// Appends glyphs, each with its index among the glyphs of all fonts.
+    pub fn upload(
+        &mut self,
+        glyphs: Vec<(u32, GlyphDrawingData)>,
+        update_queue: &mut Vec<UpdateInfo>,
+        gpu_context: &GpuContext,
+    ) {
+        if glyphs.is_empty() {
+            return;
+        }
+        let mut font_curves = Vec::new();
+        let mut hor_band_list = Vec::new();
+        let mut ver_band_list = Vec::new();
+        for (glyph_index, glyph) in glyphs {
+            let glyph_data = FontData {
+                curve_texel_index: (self.font_curves.content.len() + font_curves.len()) as u32,
+                hband_index: (self.hor_band_list.content.len() + hor_band_list.len()) as u32,
+                vband_index: (self.ver_band_list.content.len() + ver_band_list.len()) as u32,
+                ..glyph.glyph_data
+            };
+            font_curves.extend(glyph.font_curves);
+            hor_band_list.extend(glyph.hor_band_list);
+            ver_band_list.extend(glyph.ver_band_list);
+            update_queue.push(UpdateInfo {
+                target_buffer: self.font_info_buffer.clone(),
+                offset: glyph_index as u64 * std::mem::size_of::<FontData>() as u64,
+                size: wgpu::BufferSize::new(std::mem::size_of::<FontData>() as _).unwrap(),
+                content: Rc::new(bytemuck::bytes_of(&glyph_data).to_vec()),
+            });
+        }
+
+        let device = &gpu_context.device;
+        let mut grown = self.font_curves.extend(&font_curves, update_queue, device);
+        grown |= self
+            .hor_band_list
+            .extend(&hor_band_list, update_queue, device);
+        grown |= self
+            .ver_band_list
+            .extend(&ver_band_list, update_queue, device);
+        if grown {
+            self.bind_group = Rc::new(create_bind_group(
+                device,
+                &self.layout,
+                [
+                    &self.font_info_buffer,
+                    &self.font_curves.buffer,
+                    &self.hor_band_list.buffer,
+                    &self.ver_band_list.buffer,
+                    &self.font_glyph_offset_buffer,
+                ],
+            ));
+        }
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// The current bind group, replaced whenever the buffers grow.
+    #[inline]
+    pub fn bind_group(&self) -> Rc<wgpu::BindGroup> {
+        self.bind_group.clone()
+    }
+}
+
+impl<T: bytemuck::Pod> GrowableBuffer<T> {
+    fn new(label: &'static str, device: &wgpu::Device) -> Self {
+        GrowableBuffer {
+            label,
+            buffer: Rc::new(create_storage_buffer(
+                label,
+                device,
+                INITIAL_CAPACITY * std::mem::size_of::<T>() as u64,
+                false,
+            )),
+            content: Vec::new(),
+        }
+    }
+
+    /// Appends `items`, returns whether the buffer had to be replaced.
+    fn extend(
+        &mut self,
+        items: &[T],
+        update_queue: &mut Vec<UpdateInfo>,
+        device: &wgpu::Device,
+    ) -> bool {
+        if items.is_empty() {
+            return false;
+        }
+        let offset = std::mem::size_of_val(self.content.as_slice()) as u64;
+        self.content.extend_from_slice(items);
+        let content_size = std::mem::size_of_val(self.content.as_slice()) as u64;
+        if content_size <= self.buffer.size() {
+            update_queue.push(UpdateInfo {
+                target_buffer: self.buffer.clone(),
+                offset,
+                size: wgpu::BufferSize::new(content_size - offset).unwrap(),
+                content: Rc::new(bytemuck::cast_slice(items).to_vec()),
+            });
+            return false;
+        }
+
+        // the new buffer starts with everything uploaded so far, pending
+        // writes to the old one don't matter anymore
+        let mut size = self.buffer.size();
+        while size < content_size {
+            size *= 2;
+        }
+        let buffer = create_storage_buffer(self.label, device, size, true);
+        buffer
+            .slice(..content_size)
+            .get_mapped_range_mut()
+            .copy_from_slice(bytemuck::cast_slice(&self.content));
+        buffer.unmap();
+        self.buffer = Rc::new(buffer);
+
+        true
+    }
+}
+
+fn create_storage_buffer(
+    label: &str,
+    device: &wgpu::Device,
+    size: u64,
+    mapped_at_creation: bool,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation,
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffers: [&wgpu::Buffer; 5],
+) -> wgpu::BindGroup {
+    let entries: Vec<_> = buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Glyph Curve Bindgroup"),
+        layout,
+        entries: &entries,
+    })
+}
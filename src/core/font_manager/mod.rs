@@ -1,85 +1,161 @@
-use std::{path::Path, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    path::PathBuf,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use owned_ttf_parser::{AsFaceRef, OwnedFace};
+use rayon::prelude::*;
 use wgpu::util::DeviceExt;
 
 use crate::utils::{max_3number, min_3number};
 
 use self::{
-    font_data::FontData, font_graphics::FontGraphics, font_info_saving::FontDrawingData,
-    font_outline::FontOutlineData, string_data::CharData,
+    font_collection::{find_face, list_axes, FontAxis, FontSource, Variation},
+    font_data::FontData,
+    font_graphics::{CoverageCurve, FillRule, FontGraphics, GlyphPass, SubpixelOrder},
+    font_info_saving::{
+        cache_path, load_compatible_cache, CacheHeader, FontDrawingData, GlyphDrawingData,
+        PreprocessParams,
+    },
+    font_outline::FontOutlineData,
+    glyph_buffers::GlyphBuffers,
+    string_data::CharData,
+    text_layout::{layout_text, LayoutOptions, PositionedGlyph, TextMetrics},
 };
 
-use super::graphics::GpuContext;
+use super::graphics::{GpuContext, UpdateInfo};
 
-mod font_data;
+pub mod font_collection;
+pub mod font_data;
 pub mod font_graphics;
-mod font_info_saving;
+pub mod font_info_saving;
 mod font_outline;
+pub mod font_rasterizer;
+pub mod glyph_buffers;
 pub mod string_data;
+#[cfg(test)]
+mod test_font;
+pub mod text_layout;
+
+/// A font file of the fallback chain.
+struct LoadedFont {
+    face: OwnedFace,
+    name: String,
+    cache_path: PathBuf,
+    params: PreprocessParams,
+    /// Glyphs preprocessed ahead of time, without an up to date cache in
+    /// `data/font` they are built from the outlines when first drawn
+    cached: Option<FontDrawingData>,
+}
 
 pub struct FontManager {
-    font_face: OwnedFace,
-    window_size: [f32; 2],
-    font_name: String,
+    fonts: Vec<LoadedFont>,
+    /// Index of the first glyph of each font among the glyphs of all fonts
+    font_glyph_offsets: Vec<u32>,
+    reported_missing_chars: RefCell<HashSet<char>>,
+    /// Glyphs as `(font_index, glyph_id)` that texts asked for
+    requested_glyphs: RefCell<HashSet<(u32, u32)>>,
+    /// Requested glyphs not uploaded yet
+    pending_glyphs: RefCell<Vec<(u32, u32)>>,
 }
 
 impl FontManager {
-    pub fn new<P: AsRef<std::path::Path> + Clone>(
-        font_path: P,
-        window_width: u32,
-        window_height: u32,
-    ) -> Self {
-        let font_file = std::fs::read(font_path.as_ref()).expect("Unable to read font!");
-        let font_face = owned_ttf_parser::OwnedFace::from_vec(font_file, 0).unwrap();
-        let window_size = [window_width as f32, window_height as f32];
+    /// Loads the fonts of `font_sources`, characters are looked up in this
+    /// order.
+    pub fn new(font_sources: &[FontSource]) -> Self {
+        assert!(!font_sources.is_empty(), "No font to load!");
+        let fonts: Vec<_> = font_sources
+            .iter()
+            .map(|font_source| {
+                let font_path = &font_source.path;
+                let font_file = std::fs::read(font_path).expect("Unable to read font!");
+                let face_index = find_face(&font_file, &font_source.face).unwrap_or_else(|| {
+                    panic!("No face {} in {}!", font_source.face, font_path.display())
+                });
+                let mut face = owned_ttf_parser::OwnedFace::from_vec(font_file, face_index)
+                    .expect("Unable to parse font!");
+                let variations = font_source
+                    .variations
+                    .iter()
+                    .filter(|variation| {
+                        let applied = variation.apply(&mut face);
+                        if applied.is_none() {
+                            log::warn!("No axis for {} in {}", variation, font_path.display());
+                        }
+                        applied.is_some()
+                    })
+                    .copied()
+                    .collect();
+                let params = PreprocessParams {
+                    face_index,
+                    variations,
+                    ..Default::default()
+                };
+                let mut font = LoadedFont {
+                    face,
+                    name: font_path
+                        .file_name()
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                        .to_lowercase(),
+                    cache_path: cache_path(font_path, &params),
+                    params,
+                    cached: None,
+                };
+                if font.cache_path.exists() {
+                    font.cached = Self::load_drawing_data(&font);
+                }
+                font
+            })
+            .collect();
+        let font_glyph_offsets = fonts
+            .iter()
+            .scan(0, |offset, font| {
+                let font_offset = *offset;
+                *offset += font.face.as_face_ref().number_of_glyphs() as u32;
+                Some(font_offset)
+            })
+            .collect();
         FontManager {
-            font_face,
-            window_size,
-            font_name: font_path
-                .as_ref()
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-                .to_lowercase(),
+            fonts,
+            font_glyph_offsets,
+            reported_missing_chars: RefCell::new(HashSet::new()),
+            requested_glyphs: RefCell::new(HashSet::new()),
+            pending_glyphs: RefCell::new(Vec::new()),
         }
     }
 
-    pub fn prepare(&self, gpu_context: &GpuContext) -> FontGraphics {
-        let font_face = self.font_face.as_face_ref();
-
-        let mut font_save_path = Path::new("data").join("font");
-        if !font_save_path.exists() {
-            std::fs::create_dir_all(font_save_path.as_path()).unwrap();
+    /// Loads the drawing data of a font from its cache in `data/font`. A
+    /// stale cache is left for the `preprocess` tool to rebuild, the glyphs
+    /// are built when first drawn meanwhile.
+    fn load_drawing_data(font: &LoadedFont) -> Option<FontDrawingData> {
+        let header = CacheHeader::new(font.face.as_slice(), font.params.clone());
+        // caches built by `preprocess` with other band options draw the same
+        // glyphs
+        let cached = load_compatible_cache(&font.cache_path, &header);
+        if cached.is_none() {
+            log::warn!(
+                "Stale glyph cache {}, run preprocess to rebuild it",
+                font.cache_path.display()
+            );
         }
-        let mut file_name = self.font_name.clone();
-        file_name.push_str(".bin");
-        font_save_path = font_save_path.join(file_name);
-
-        let font_drawing_data: FontDrawingData = if font_save_path.exists() {
-            bincode::deserialize(&std::fs::read(font_save_path.as_path()).unwrap()).unwrap_or_else(
-                |_| {
-                    let data = get_font_drawing_data(font_face);
-                    std::fs::write(
-                        font_save_path,
-                        bincode::serialize(&data).expect("Unable to serialize font data!"),
-                    )
-                    .unwrap();
-                    data
-                },
-            )
-        } else {
-            let data = get_font_drawing_data(font_face);
-            std::fs::write(
-                font_save_path,
-                bincode::serialize(&data).expect("Unable to deserialize font data!"),
-            )
-            .unwrap();
-            data
-        };
+        cached.map(|(_, data)| data)
+    }
 
+    /// Builds the pipeline drawing the glyphs, `window_size` is in physical
+    /// pixels. Glyphs are uploaded later as texts need them.
+    pub fn prepare(
+        &self,
+        gpu_context: &GpuContext,
+        window_size: [f32; 2],
+        coverage_curve: CoverageCurve,
+        subpixel_order: SubpixelOrder,
+    ) -> FontGraphics {
         // shader config
         let draw_shader = gpu_context
             .device
@@ -121,7 +197,7 @@ impl FontManager {
         let string_vec_buffer_layout = wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<CharData>() as u64,
             step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &wgpu::vertex_attr_array![1 => Uint32, 2 => Float32x2, 3 => Float32],
+            attributes: &wgpu::vertex_attr_array![1 => Uint32, 2 => Float32x2, 3 => Float32, 4 => Uint32, 5 => Float32x4, 6 => Float32x4],
         };
 
         let window_info_buffer =
@@ -129,55 +205,42 @@ impl FontManager {
                 .device
                 .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some("Window Size Info"),
-                    contents: bytemuck::cast_slice(&self.window_size),
-                    usage: wgpu::BufferUsages::UNIFORM,
+                    contents: bytemuck::cast_slice(&window_size),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
                 });
-        let uniform_bindgroup_layout =
+        // colors are sRGB, the shader decodes them when the target encodes
+        let srgb_target = gpu_context.surface_format.describe().srgb;
+        let color_info = [
+            coverage_curve.gamma,
+            coverage_curve.contrast,
+            if srgb_target { 1.0 } else { 0.0 },
+            match subpixel_order {
+                SubpixelOrder::Rgb => 0.0,
+                SubpixelOrder::Bgr => 1.0,
+            },
+        ];
+        let color_info_buffer =
             gpu_context
                 .device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: Some("Uniform Bindgroup"),
-                    entries: &[wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(
-                                std::mem::size_of::<[f32; 2]>() as _,
-                            ),
-                        },
-                        count: None,
-                    }],
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Color Info"),
+                    contents: bytemuck::cast_slice(&color_info),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
                 });
-        let uniform_bindgroup = gpu_context
-            .device
-            .create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("Uniform Bindgroup"),
-                layout: &uniform_bindgroup_layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: window_info_buffer.as_entire_binding(),
-                }],
-            });
-
-        let font_data_mem_size = std::mem::size_of::<FontData>();
-        let font_data_size = font_drawing_data.font_data.len();
-        let font_texture_size = font_drawing_data.font_curves.len();
-        let font_bindgroup_layout =
+        let uniform_bindgroup_layout =
             gpu_context
                 .device
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: Some("Font Data"),
+                    label: Some("Uniform Bindgroup"),
                     entries: &[
                         wgpu::BindGroupLayoutEntry {
                             binding: 0,
-                            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                            visibility: wgpu::ShaderStages::VERTEX,
                             ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                ty: wgpu::BufferBindingType::Uniform,
                                 has_dynamic_offset: false,
                                 min_binding_size: wgpu::BufferSize::new(
-                                    (font_data_mem_size * font_data_size) as _,
+                                    std::mem::size_of::<[f32; 2]>() as _,
                                 ),
                             },
                             count: None,
@@ -186,120 +249,65 @@ impl FontManager {
                             binding: 1,
                             visibility: wgpu::ShaderStages::FRAGMENT,
                             ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Storage { read_only: true },
-                                has_dynamic_offset: false,
-                                min_binding_size: wgpu::BufferSize::new(
-                                    (font_texture_size * std::mem::size_of::<[f32; 4]>()) as _,
-                                ),
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 2,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Storage { read_only: true },
-                                has_dynamic_offset: false,
-                                min_binding_size: wgpu::BufferSize::new(
-                                    (font_drawing_data.hor_band_list.len()
-                                        * std::mem::size_of::<u32>())
-                                        as _,
-                                ),
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 3,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                ty: wgpu::BufferBindingType::Uniform,
                                 has_dynamic_offset: false,
                                 min_binding_size: wgpu::BufferSize::new(
-                                    (font_drawing_data.ver_band_list.len()
-                                        * std::mem::size_of::<u32>())
-                                        as _,
+                                    std::mem::size_of::<[f32; 4]>() as _,
                                 ),
                             },
                             count: None,
                         },
                     ],
                 });
-        let font_info_buffer =
-            gpu_context
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Font Rect Buffer"),
-                    contents: bytemuck::cast_slice(&font_drawing_data.font_data),
-                    usage: wgpu::BufferUsages::STORAGE,
-                });
-        let font_curves_buffer =
-            gpu_context
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Glyph Curve Buffer"),
-                    contents: bytemuck::cast_slice(&font_drawing_data.font_curves),
-                    usage: wgpu::BufferUsages::STORAGE,
-                });
-        let horizontal_band_buffer =
-            gpu_context
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Glyph Curve Ordering Buffer"),
-                    contents: bytemuck::cast_slice(&font_drawing_data.hor_band_list),
-                    usage: wgpu::BufferUsages::STORAGE,
-                });
-        let vertical_band_buffer =
-            gpu_context
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Glyph Curve Ordering Buffer"),
-                    contents: bytemuck::cast_slice(&font_drawing_data.ver_band_list),
-                    usage: wgpu::BufferUsages::STORAGE,
-                });
-        let font_data_bindgroup =
-            gpu_context
-                .device
-                .create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("Glyph Curve Bindgroup"),
-                    layout: &font_bindgroup_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: font_info_buffer.as_entire_binding(),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: font_curves_buffer.as_entire_binding(),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 2,
-                            resource: horizontal_band_buffer.as_entire_binding(),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 3,
-                            resource: vertical_band_buffer.as_entire_binding(),
-                        },
-                    ],
-                });
+        let uniform_bindgroup = gpu_context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Uniform Bindgroup"),
+                layout: &uniform_bindgroup_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: window_info_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: color_info_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+        // all fonts share the same buffers, the shader finds the glyphs of a
+        // font from its offset in the glyph list
+        let glyph_buffers = GlyphBuffers::new(
+            gpu_context,
+            &self.font_glyph_offsets,
+            self.fonts
+                .iter()
+                .map(|font| font.face.as_face_ref().number_of_glyphs() as usize)
+                .sum(),
+        );
 
         let rp_layout =
             gpu_context
                 .device
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                     label: Some("Renderpipeline Layout"),
-                    bind_group_layouts: &[&uniform_bindgroup_layout, &font_bindgroup_layout],
+                    bind_group_layouts: &[&uniform_bindgroup_layout, glyph_buffers.layout()],
                     push_constant_ranges: &[],
                 });
-        let render_pipeline =
+        let create_pipeline = |fill_rule: FillRule, pass: GlyphPass| {
             gpu_context
                 .device
                 .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: Some("Render Pipeline"),
+                    label: Some(&format!("{:?} {:?} Render Pipeline", fill_rule, pass)),
                     layout: Some(&rp_layout),
                     vertex: wgpu::VertexState {
                         module: &draw_shader,
                         entry_point: "vs_main",
-                        buffers: &[vertex_buffer_layout, string_vec_buffer_layout],
+                        buffers: &[
+                            vertex_buffer_layout.clone(),
+                            string_vec_buffer_layout.clone(),
+                        ],
                     },
                     primitive: wgpu::PrimitiveState {
                         topology: wgpu::PrimitiveTopology::TriangleList,
@@ -314,36 +322,121 @@ impl FontManager {
                     multisample: wgpu::MultisampleState::default(),
                     fragment: Some(wgpu::FragmentState {
                         module: &draw_shader,
-                        entry_point: "fs_main",
+                        entry_point: pass.entry_point(fill_rule),
                         targets: &[Some(wgpu::ColorTargetState {
-                            format: gpu_context
-                                .surface
-                                .get_capabilities(&gpu_context.adapter)
-                                .formats[0],
-                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                            format: gpu_context.surface_format,
+                            blend: Some(pass.blend()),
                             write_mask: wgpu::ColorWrites::ALL,
                         })],
                     }),
                     multiview: None,
-                });
+                })
+        };
 
         FontGraphics {
             vertex_buffer: Rc::new(vertex_buffer),
             index_buffer: Rc::new(index_buffer),
-            render_pipeline: Rc::new(render_pipeline),
+            render_pipelines: [FillRule::NonZero, FillRule::EvenOdd]
+                .into_iter()
+                .flat_map(|fill_rule| {
+                    GlyphPass::ALL.map(|pass| Rc::new(create_pipeline(fill_rule, pass)))
+                })
+                .collect(),
+            window_info_buffer: Rc::new(window_info_buffer),
+            color_info_buffer: Rc::new(color_info_buffer),
             uniform_bindgroup: Rc::new(uniform_bindgroup),
-            font_data_bindgroup: Rc::new(font_data_bindgroup),
+            glyph_buffers,
         }
     }
 
+    /// Variation axes of a font, empty unless it is a variable font.
+    pub fn axes(&self, font_index: usize) -> Vec<FontAxis> {
+        list_axes(self.fonts[font_index].face.as_face_ref())
+    }
+
+    /// Axis coordinates of a font instance, axes left out are at their
+    /// default.
     #[inline]
-    pub fn get_face(&self) -> &owned_ttf_parser::Face {
-        self.font_face.as_face_ref()
+    pub fn variations(&self, font_index: usize) -> &[Variation] {
+        &self.fonts[font_index].params.variations
     }
 
+    /// Faces of the fallback chain, in lookup order.
     #[inline]
-    pub fn get_window_size(&self) -> [f32; 2] {
-        self.window_size
+    pub fn get_faces(&self) -> Vec<&owned_ttf_parser::Face<'_>> {
+        self.fonts
+            .iter()
+            .map(|font| font.face.as_face_ref())
+            .collect()
+    }
+
+    /// Measures `s` laid out at `font_size` pixels the way a text would be,
+    /// without drawing it.
+    pub fn measure_text(&self, s: &str, font_size: f32, options: &LayoutOptions) -> TextMetrics {
+        layout_text(&self.get_faces(), s, font_size, options).metrics()
+    }
+
+    /// Marks glyphs a text is about to draw, the new ones are uploaded with
+    /// the next `upload_glyphs`.
+    pub fn request_glyphs(&self, glyphs: &[PositionedGlyph]) {
+        let mut requested = self.requested_glyphs.borrow_mut();
+        let mut pending = self.pending_glyphs.borrow_mut();
+        for glyph in glyphs {
+            if requested.insert((glyph.font_index, glyph.glyph_id)) {
+                pending.push((glyph.font_index, glyph.glyph_id));
+            }
+        }
+    }
+
+    /// Uploads the glyphs requested since the last call, reading them from
+    /// the cache of their font or preprocessing their outlines.
+    pub fn upload_glyphs(
+        &self,
+        font_graphics: &mut FontGraphics,
+        update_queue: &mut Vec<UpdateInfo>,
+        gpu_context: &GpuContext,
+    ) {
+        let pending = std::mem::take(&mut *self.pending_glyphs.borrow_mut());
+        let glyphs = pending
+            .into_iter()
+            .filter_map(|(font_index, glyph_id)| {
+                let font = &self.fonts[font_index as usize];
+                let glyph = match &font.cached {
+                    Some(cached) => cached.glyph(glyph_id),
+                    None => get_glyph_drawing_data(
+                        font.face.as_face_ref(),
+                        glyph_id as u16,
+                        &font.params,
+                    ),
+                }?;
+                Some((
+                    self.font_glyph_offsets[font_index as usize] + glyph_id,
+                    glyph,
+                ))
+            })
+            .collect();
+        font_graphics
+            .glyph_buffers
+            .upload(glyphs, update_queue, gpu_context);
+    }
+
+    /// Logs characters the font has no glyph for, once per character.
+    pub fn report_missing_chars(&self, missing_chars: &[char]) {
+        let mut reported = self.reported_missing_chars.borrow_mut();
+        for &missing_char in missing_chars {
+            if reported.insert(missing_char) {
+                log::warn!(
+                    "No glyph for {:?} (U+{:04X}) in {}",
+                    missing_char,
+                    missing_char as u32,
+                    self.fonts
+                        .iter()
+                        .map(|font| font.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
     }
 }
 
@@ -380,111 +473,185 @@ fn band_process(
     }
 }
 
-fn get_font_drawing_data(font_face: &owned_ttf_parser::Face) -> FontDrawingData {
+/// Preprocesses every glyph of the font.
+pub fn get_font_drawing_data(
+    font_face: &owned_ttf_parser::Face,
+    params: &PreprocessParams,
+) -> FontDrawingData {
+    get_font_drawing_data_with_progress(font_face, params, |_, _| {})
+}
+
+/// Preprocesses every glyph of the font on the rayon thread pool, calling
+/// `progress` with the glyphs done so far and the glyph count, as the
+/// `preprocess` tool reports it. `progress` is called from the worker threads.
+pub fn get_font_drawing_data_with_progress<F>(
+    font_face: &owned_ttf_parser::Face,
+    params: &PreprocessParams,
+    progress: F,
+) -> FontDrawingData
+where
+    F: Fn(usize, usize) + Sync,
+{
+    let glyph_count = font_face.number_of_glyphs() as usize;
+    let done = AtomicUsize::new(0);
+    let glyphs: Vec<_> = (0..font_face.number_of_glyphs())
+        .into_par_iter()
+        .map(|glyph_id| {
+            let glyph = get_glyph_drawing_data(font_face, glyph_id, params);
+            progress(done.fetch_add(1, Ordering::Relaxed) + 1, glyph_count);
+            glyph
+        })
+        .collect();
+
+    // glyphs come back in order, each one is moved past the curves and
+    // bands of the ones before it
+    let mut data = FontDrawingData::default();
+    for glyph in glyphs {
+        data.push_glyph(glyph);
+    }
+    log::debug!(
+        "Preprocessed {} glyphs, {} without outline",
+        glyph_count,
+        data.font_data
+            .iter()
+            .filter(|glyph_data| glyph_data.width_in_em < 0.0)
+            .count()
+    );
+
+    data
+}
+
+/// Splits the outline of a glyph into bands of curves, `None` for glyphs
+/// without outline.
+pub fn get_glyph_drawing_data(
+    font_face: &owned_ttf_parser::Face,
+    glyph_id: u16,
+    params: &PreprocessParams,
+) -> Option<GlyphDrawingData> {
     let units_per_em = font_face.units_per_em() as f32;
-    let mut font_data = Vec::new();
     let mut font_curves = Vec::new();
     let mut hor_band_list = Vec::new(); // from bottom to top
     let mut ver_band_list = Vec::new(); // from left to right
     let mut curves_index = 0;
 
-    for glyph_id in 0..font_face.number_of_glyphs() {
-        let mut this_char = FontOutlineData::new();
+    let mut this_char = FontOutlineData::new();
 
-        let Some(bounding_box) = font_face
-                .outline_glyph(owned_ttf_parser::GlyphId(glyph_id), &mut this_char)
-                else {
-                    font_data.push(FontData::empty());
-                    println!("Skiped glyph: {}", glyph_id);
-                    continue;
-                };
+    let bounding_box =
+        font_face.outline_glyph(owned_ttf_parser::GlyphId(glyph_id), &mut this_char)?;
 
-        let this_char_curve_count = this_char.number_of_curves();
-        let mut band_count = this_char_curve_count / 8; // 8 curves per band
-        if band_count < 2 {
-            band_count = 2;
-        }
-        if band_count > 16 {
-            band_count = 16;
-        }
-        let hor_band_size = bounding_box.height() as f32 / units_per_em / band_count as f32;
-        let ver_band_size = bounding_box.width() as f32 / units_per_em / band_count as f32;
-
-        // processing
-        let mut point0_x = 0.0;
-        let mut point0_y = 0.0;
-        let origin_x = bounding_box.x_min as f32 / units_per_em;
-        let origin_y = bounding_box.y_min as f32 / units_per_em;
-        let this_char_curve_start = curves_index;
-        font_data.push(FontData::new(
-            this_char_curve_start,
-            hor_band_list.len(),
-            ver_band_list.len(),
-            band_count as u32,
-            &bounding_box,
-            units_per_em,
-        ));
+    let this_char_curve_count = this_char.number_of_curves(params.cubic_tolerance * units_per_em);
+    let mut band_count = this_char_curve_count / params.curves_per_band as usize;
+    if band_count < params.min_band_count as usize {
+        band_count = params.min_band_count as usize;
+    }
+    if band_count > params.max_band_count as usize {
+        band_count = params.max_band_count as usize;
+    }
+    let hor_band_size = bounding_box.height() as f32 / units_per_em / band_count as f32;
+    let ver_band_size = bounding_box.width() as f32 / units_per_em / band_count as f32;
 
-        let epsilon = 0.000001;
-        let mut hor_band_temp = Vec::new();
-        let mut ver_band_temp = Vec::new();
-        for _ in 0..band_count {
-            hor_band_temp.push(Vec::new());
-            ver_band_temp.push(Vec::new());
-        }
-        for command in this_char.point_command_iter() {
-            match *command {
-                font_outline::OutlineDrawCommand::MoveTo(a, b) => {
-                    point0_x = a / units_per_em - origin_x;
-                    point0_y = b / units_per_em - origin_y;
-                    font_curves.push([-1.0, -1.0, point0_x, point0_y]);
-                    curves_index += 1;
-                }
-                font_outline::OutlineDrawCommand::LineTo(a, b) => {
-                    let point2_x = a / units_per_em - origin_x;
-                    let point2_y = b / units_per_em - origin_y;
-                    let point1_x = (point2_x + point0_x) / 2.0;
-                    let point1_y = (point2_y + point0_y) / 2.0;
-                    font_curves.push([point1_x, point1_y, point2_x, point2_y]);
+    // processing
+    let mut point0_x = 0.0;
+    let mut point0_y = 0.0;
+    let origin_x = bounding_box.x_min as f32 / units_per_em;
+    let origin_y = bounding_box.y_min as f32 / units_per_em;
+    let glyph_data = FontData::new(0, 0, 0, band_count as u32, &bounding_box, units_per_em);
 
-                    let this_char_glyph_offset = curves_index - this_char_curve_start;
-                    // horizontal band detect
-                    if (point2_y - point0_y).abs() > epsilon {
-                        // reject horizontal line, cause it makes no contribute to winding number
-                        band_process(
-                            true,
-                            hor_band_size,
-                            [point0_x, point0_y],
-                            [point1_x, point1_y],
-                            [point2_x, point2_y],
-                            this_char_glyph_offset,
-                            &mut hor_band_temp,
-                        );
-                    }
-                    // vertical band detect
-                    if (point2_x - point0_x).abs() > epsilon {
-                        // reject vertical line, cause it makes no contribute to winding number
-                        band_process(
-                            false,
-                            ver_band_size,
-                            [point0_x, point0_y],
-                            [point1_x, point1_y],
-                            [point2_x, point2_y],
-                            this_char_glyph_offset,
-                            &mut ver_band_temp,
-                        );
-                    }
-                    point0_x = point2_x;
-                    point0_y = point2_y;
-                    curves_index += 1;
+    let epsilon = 0.000001;
+    let mut hor_band_temp = Vec::new();
+    let mut ver_band_temp = Vec::new();
+    for _ in 0..band_count {
+        hor_band_temp.push(Vec::new());
+        ver_band_temp.push(Vec::new());
+    }
+    for command in this_char.point_command_iter() {
+        match *command {
+            font_outline::OutlineDrawCommand::MoveTo(a, b) => {
+                point0_x = a / units_per_em - origin_x;
+                point0_y = b / units_per_em - origin_y;
+                font_curves.push([-1.0, -1.0, point0_x, point0_y]);
+                curves_index += 1;
+            }
+            font_outline::OutlineDrawCommand::LineTo(a, b) => {
+                let point2_x = a / units_per_em - origin_x;
+                let point2_y = b / units_per_em - origin_y;
+                let point1_x = (point2_x + point0_x) / 2.0;
+                let point1_y = (point2_y + point0_y) / 2.0;
+                font_curves.push([point1_x, point1_y, point2_x, point2_y]);
+
+                let this_char_glyph_offset = curves_index;
+                // horizontal band detect
+                if (point2_y - point0_y).abs() > epsilon {
+                    // reject horizontal line, cause it makes no contribute to winding number
+                    band_process(
+                        true,
+                        hor_band_size,
+                        [point0_x, point0_y],
+                        [point1_x, point1_y],
+                        [point2_x, point2_y],
+                        this_char_glyph_offset,
+                        &mut hor_band_temp,
+                    );
                 }
-                font_outline::OutlineDrawCommand::QuadTo(a1, b1, a, b) => {
-                    let point1_x = a1 / units_per_em - origin_x;
-                    let point1_y = b1 / units_per_em - origin_y;
-                    let point2_x = a / units_per_em - origin_x;
-                    let point2_y = b / units_per_em - origin_y;
+                // vertical band detect
+                if (point2_x - point0_x).abs() > epsilon {
+                    // reject vertical line, cause it makes no contribute to winding number
+                    band_process(
+                        false,
+                        ver_band_size,
+                        [point0_x, point0_y],
+                        [point1_x, point1_y],
+                        [point2_x, point2_y],
+                        this_char_glyph_offset,
+                        &mut ver_band_temp,
+                    );
+                }
+                point0_x = point2_x;
+                point0_y = point2_y;
+                curves_index += 1;
+            }
+            font_outline::OutlineDrawCommand::QuadTo(a1, b1, a, b) => {
+                let point1_x = a1 / units_per_em - origin_x;
+                let point1_y = b1 / units_per_em - origin_y;
+                let point2_x = a / units_per_em - origin_x;
+                let point2_y = b / units_per_em - origin_y;
+                font_curves.push([point1_x, point1_y, point2_x, point2_y]);
+                let this_char_glyph_offset = curves_index;
+                // horizontal band detect
+                band_process(
+                    true,
+                    hor_band_size,
+                    [point0_x, point0_y],
+                    [point1_x, point1_y],
+                    [point2_x, point2_y],
+                    this_char_glyph_offset,
+                    &mut hor_band_temp,
+                );
+                // vertical band detect
+                band_process(
+                    false,
+                    ver_band_size,
+                    [point0_x, point0_y],
+                    [point1_x, point1_y],
+                    [point2_x, point2_y],
+                    this_char_glyph_offset,
+                    &mut ver_band_temp,
+                );
+                point0_x = point2_x;
+                point0_y = point2_y;
+                curves_index += 1;
+            }
+            font_outline::OutlineDrawCommand::CurveTo(a1, b1, a2, b2, a, b) => {
+                let quads = font_outline::cubic_to_quadratics(
+                    [point0_x, point0_y],
+                    [a1 / units_per_em - origin_x, b1 / units_per_em - origin_y],
+                    [a2 / units_per_em - origin_x, b2 / units_per_em - origin_y],
+                    [a / units_per_em - origin_x, b / units_per_em - origin_y],
+                    params.cubic_tolerance,
+                );
+                for ([point1_x, point1_y], [point2_x, point2_y]) in quads {
                     font_curves.push([point1_x, point1_y, point2_x, point2_y]);
-                    let this_char_glyph_offset = curves_index - this_char_curve_start;
+                    let this_char_glyph_offset = curves_index;
                     // horizontal band detect
                     band_process(
                         true,
@@ -509,39 +676,38 @@ fn get_font_drawing_data(font_face: &owned_ttf_parser::Face) -> FontDrawingData
                     point0_y = point2_y;
                     curves_index += 1;
                 }
-                font_outline::OutlineDrawCommand::CurveTo(_, _, _, _, _, _) => unreachable!(),
-                font_outline::OutlineDrawCommand::Close => {}
             }
+            font_outline::OutlineDrawCommand::Close => {}
         }
+    }
 
-        let mut hcount = 2 * band_count;
-        let mut vcount = 2 * band_count;
-        for index in 0..band_count {
-            hor_band_list.push(hcount as u32);
-            hor_band_list.push(hor_band_temp[index].len() as u32);
-            hor_band_temp[index].sort_by(|(max0, _), (max1, _)| max1.partial_cmp(max0).unwrap());
-            hcount += hor_band_temp[index].len();
-
-            ver_band_list.push(vcount as u32);
-            ver_band_list.push(ver_band_temp[index].len() as u32);
-            ver_band_temp[index].sort_by(|(max0, _), (max1, _)| max1.partial_cmp(max0).unwrap());
-            vcount += ver_band_temp[index].len();
-        }
+    let mut hcount = 2 * band_count;
+    let mut vcount = 2 * band_count;
+    for index in 0..band_count {
+        hor_band_list.push(hcount as u32);
+        hor_band_list.push(hor_band_temp[index].len() as u32);
+        hor_band_temp[index].sort_by(|(max0, _), (max1, _)| max1.partial_cmp(max0).unwrap());
+        hcount += hor_band_temp[index].len();
 
-        for index in 0..band_count {
-            for (_, offset) in hor_band_temp[index].iter() {
-                hor_band_list.push(*offset);
-            }
-            for (_, offset) in ver_band_temp[index].iter() {
-                ver_band_list.push(*offset);
-            }
+        ver_band_list.push(vcount as u32);
+        ver_band_list.push(ver_band_temp[index].len() as u32);
+        ver_band_temp[index].sort_by(|(max0, _), (max1, _)| max1.partial_cmp(max0).unwrap());
+        vcount += ver_band_temp[index].len();
+    }
+
+    for index in 0..band_count {
+        for (_, offset) in hor_band_temp[index].iter() {
+            hor_band_list.push(*offset);
+        }
+        for (_, offset) in ver_band_temp[index].iter() {
+            ver_band_list.push(*offset);
         }
     }
 
-    FontDrawingData {
-        font_data,
+    Some(GlyphDrawingData {
+        glyph_data,
         font_curves,
         hor_band_list,
         ver_band_list,
-    }
+    })
 }
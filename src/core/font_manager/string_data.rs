@@ -4,14 +4,28 @@ pub struct CharData {
     glyph_id: u32,
     coordinate: [f32; 2], // base line position
     pixels_per_em: f32,
+    font_index: u32, // index in the fallback chain
+    color: [f32; 4], // straight alpha, premultiplied in the shader
+    // offset x and y, dilation and blur radius in pixels, zero for fills
+    effect: [f32; 4],
 }
 
 impl CharData {
-    pub fn new(glyph_id: u32, pixels_per_em: f32, pos: [f32; 2]) -> Self {
+    pub fn new(
+        font_index: u32,
+        glyph_id: u32,
+        pixels_per_em: f32,
+        pos: [f32; 2],
+        color: [f32; 4],
+        effect: [f32; 4],
+    ) -> Self {
         CharData {
             coordinate: pos,
             glyph_id,
             pixels_per_em,
+            font_index,
+            color,
+            effect,
         }
     }
 }
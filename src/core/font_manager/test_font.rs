@@ -0,0 +1,422 @@
+//! Fonts and preprocessed glyphs for tests: `data/eng1.ttf`, and minimal
+//! TrueType fonts with outlines made of straight lines and metrics picked by
+//! the test, optionally named, variable and gathered in collections.
+
+use owned_ttf_parser::Face;
+
+use super::{
+    font_info_saving::{FontDrawingData, PreprocessParams},
+    get_glyph_drawing_data,
+};
+
+/// The font file of `data/eng1.ttf`.
+pub fn eng1() -> Vec<u8> {
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/data/eng1.ttf")).unwrap()
+}
+
+/// Drawing data with the glyphs of `chars` from `face` at their glyph ids,
+/// `None` for the glyphs before and between them.
+pub fn drawing_data(face: &Face, chars: &str, params: &PreprocessParams) -> FontDrawingData {
+    let glyph_ids: Vec<u16> = chars
+        .chars()
+        .map(|c| face.glyph_index(c).unwrap().0)
+        .collect();
+    let mut drawing_data = FontDrawingData::default();
+    for id in 0..=*glyph_ids.iter().max().unwrap() {
+        drawing_data.push_glyph(if glyph_ids.contains(&id) {
+            get_glyph_drawing_data(face, id, params)
+        } else {
+            None
+        });
+    }
+    drawing_data
+}
+
+/// Drawing data holding only the glyph of `c` from `font_file`, and the id of
+/// the glyph.
+pub fn glyph_of(font_file: &[u8], c: char) -> (FontDrawingData, u32) {
+    let face = Face::parse(font_file, 0).unwrap();
+    let glyph_id = face.glyph_index(c).unwrap().0;
+    let drawing_data = drawing_data(&face, &c.to_string(), &PreprocessParams::default());
+    (drawing_data, glyph_id as u32)
+}
+
+/// A glyph mapped to `c`, each contour a closed polygon in font units,
+/// clockwise contours fill and counterclockwise ones cut holes.
+pub struct TestGlyph {
+    pub c: char,
+    pub contours: Vec<Vec<[i16; 2]>>,
+    /// In font units
+    pub advance: u16,
+}
+
+/// Axis aligned rectangle from `min` to `max`, wound clockwise unless
+/// `counterclockwise`.
+pub fn rectangle(min: [i16; 2], max: [i16; 2], counterclockwise: bool) -> Vec<[i16; 2]> {
+    let mut points = vec![
+        [min[0], min[1]],
+        [min[0], max[1]],
+        [max[0], max[1]],
+        [max[0], min[1]],
+    ];
+    if counterclockwise {
+        points.reverse();
+    }
+    points
+}
+
+/// A font with 1000 units per em. Glyph 0 is an empty `.notdef` 1000 units
+/// wide, the glyphs follow in order and must be sorted by
+/// character. `ligatures` replace the characters of a string with the glyph
+/// of a character, each starting with a different character. `kerning`
+/// adjusts the advance between two characters, in font units.
+pub fn build(
+    ascender: i16,
+    descender: i16,
+    glyphs: &[TestGlyph],
+    ligatures: &[(&str, char)],
+    kerning: &[(char, char, i16)],
+) -> Vec<u8> {
+    let glyph_count = glyphs.len() as u16 + 1;
+    let mut glyf = Vec::new();
+    // `.notdef` starts and ends at 0
+    let mut loca = vec![0u32, 0];
+    for glyph in glyphs {
+        glyf.extend(glyph_outline(&glyph.contours));
+        loca.push(glyf.len() as u32);
+    }
+
+    let mut head = Vec::new();
+    for value in [0x10000u32, 0x10000, 0, 0x5F0F3CF5] {
+        head.extend(value.to_be_bytes());
+    }
+    head.extend(0u16.to_be_bytes()); // flags
+    head.extend(1000u16.to_be_bytes()); // units per em
+    head.extend([0; 16]); // created and modified
+    for value in [0i16, descender, 1000, ascender] {
+        head.extend(value.to_be_bytes());
+    }
+    for value in [0u16, 8, 2] {
+        head.extend(value.to_be_bytes());
+    }
+    head.extend(1i16.to_be_bytes()); // long offsets
+    head.extend(0i16.to_be_bytes());
+
+    let mut hhea = Vec::new();
+    hhea.extend(0x10000u32.to_be_bytes());
+    for value in [ascender, descender, 0] {
+        hhea.extend(value.to_be_bytes());
+    }
+    let max_advance = glyphs
+        .iter()
+        .map(|glyph| glyph.advance)
+        .fold(1000, u16::max);
+    hhea.extend(max_advance.to_be_bytes());
+    for value in [0i16, 0, 1000, 1, 0, 0, 0, 0, 0, 0, 0] {
+        hhea.extend(value.to_be_bytes());
+    }
+    hhea.extend(glyph_count.to_be_bytes());
+
+    let mut maxp = Vec::new();
+    maxp.extend(0x5000u32.to_be_bytes());
+    maxp.extend(glyph_count.to_be_bytes());
+
+    // left side bearings are left at 0, layouts take them from the bounding
+    // boxes
+    let hmtx: Vec<u8> = std::iter::once(1000)
+        .chain(glyphs.iter().map(|glyph| glyph.advance))
+        .flat_map(|advance| [advance.to_be_bytes(), 0u16.to_be_bytes()].concat())
+        .collect();
+
+    // a format 4 segment per character, the delta maps it to its glyph
+    let mut segments: Vec<(u16, u16)> = glyphs
+        .iter()
+        .enumerate()
+        .map(|(index, glyph)| {
+            let c = glyph.c as u32 as u16;
+            (c, (index as u16 + 1).wrapping_sub(c))
+        })
+        .collect();
+    segments.push((0xFFFF, 1));
+    let segment_count = segments.len() as u16;
+    let mut subtable = Vec::new();
+    for value in [4, 16 + 8 * segment_count, 0, segment_count * 2, 0, 0, 0] {
+        subtable.extend(value.to_be_bytes());
+    }
+    subtable.extend(segments.iter().flat_map(|(c, _)| c.to_be_bytes()));
+    subtable.extend([0, 0]);
+    subtable.extend(segments.iter().flat_map(|(c, _)| c.to_be_bytes()));
+    subtable.extend(segments.iter().flat_map(|(_, delta)| delta.to_be_bytes()));
+    subtable.extend(segments.iter().flat_map(|_| 0u16.to_be_bytes()));
+    let mut cmap = Vec::new();
+    for value in [0u16, 1, 3, 1] {
+        cmap.extend(value.to_be_bytes());
+    }
+    cmap.extend(12u32.to_be_bytes());
+    cmap.extend(subtable);
+
+    let glyph_id = |c: char| {
+        glyphs
+            .iter()
+            .position(|glyph| glyph.c == c)
+            .expect("Unable to find the glyph of a ligature or kerning pair!") as u16
+            + 1
+    };
+    let mut ligatures: Vec<(Vec<u16>, u16)> = ligatures
+        .iter()
+        .map(|(components, c)| (components.chars().map(glyph_id).collect(), glyph_id(*c)))
+        .collect();
+    ligatures.sort_by_key(|(components, _)| components[0]);
+    let mut kerning: Vec<(u16, u16, i16)> = kerning
+        .iter()
+        .map(|(left, right, value)| (glyph_id(*left), glyph_id(*right), *value))
+        .collect();
+    kerning.sort();
+
+    assemble(vec![
+        (*b"GSUB", gsub(&ligatures)),
+        (*b"cmap", cmap),
+        (*b"glyf", glyf),
+        (*b"head", head),
+        (*b"hhea", hhea),
+        (*b"hmtx", hmtx),
+        (*b"kern", kern(&kerning)),
+        (
+            *b"loca",
+            loca.iter()
+                .flat_map(|offset| offset.to_be_bytes())
+                .collect(),
+        ),
+        (*b"maxp", maxp),
+    ])
+}
+
+/// `font` with a `name` table naming its family and style, the full name is
+/// both.
+pub fn named(font: &[u8], family: &str, style: &str) -> Vec<u8> {
+    let full_name = format!("{} {}", family, style);
+    let names = [(1u16, family), (2, style), (4, full_name.as_str())];
+    let mut records = Vec::new();
+    let mut strings = Vec::new();
+    for (name_id, name) in names {
+        let string: Vec<u8> = name.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        // Windows platform, Unicode BMP encoding, US English
+        for value in [
+            3u16,
+            1,
+            0x409,
+            name_id,
+            string.len() as u16,
+            strings.len() as u16,
+        ] {
+            records.extend(value.to_be_bytes());
+        }
+        strings.extend(string);
+    }
+    let mut name = Vec::new();
+    for value in [0u16, names.len() as u16, 6 + records.len() as u16] {
+        name.extend(value.to_be_bytes());
+    }
+    name.extend(records);
+    name.extend(strings);
+
+    with_table(font, *b"name", name)
+}
+
+/// `font` made variable with an `fvar` table of `axes`, each a tag with its
+/// min, default and max values.
+pub fn variable(font: &[u8], axes: &[([u8; 4], f32, f32, f32)]) -> Vec<u8> {
+    let axis_count = axes.len() as u16;
+    let mut fvar = Vec::new();
+    // no named instances
+    for value in [1u16, 0, 16, 2, axis_count, 20, 0, 4 + 4 * axis_count] {
+        fvar.extend(value.to_be_bytes());
+    }
+    for (tag, min_value, default_value, max_value) in axes {
+        fvar.extend(tag);
+        for value in [min_value, default_value, max_value] {
+            fvar.extend(((value * 65536.0) as i32).to_be_bytes());
+        }
+        fvar.extend([0; 4]); // flags and name id
+    }
+
+    with_table(font, *b"fvar", fvar)
+}
+
+/// A font collection (.ttc) holding `fonts` in order.
+pub fn collection(fonts: &[Vec<u8>]) -> Vec<u8> {
+    let mut collection = Vec::new();
+    collection.extend(b"ttcf");
+    collection.extend(0x10000u32.to_be_bytes());
+    collection.extend((fonts.len() as u32).to_be_bytes());
+    let mut offset = 12 + 4 * fonts.len();
+    for font in fonts {
+        collection.extend((offset as u32).to_be_bytes());
+        offset += font.len().next_multiple_of(4);
+    }
+    // table offsets count from the start of the collection
+    for font in fonts {
+        let font_offset = collection.len() as u32;
+        let mut font = font.clone();
+        let table_count = u16::from_be_bytes([font[4], font[5]]) as usize;
+        for record in 0..table_count {
+            let start = 12 + 16 * record + 8;
+            let table_offset = u32::from_be_bytes(font[start..start + 4].try_into().unwrap());
+            font[start..start + 4].copy_from_slice(&(table_offset + font_offset).to_be_bytes());
+        }
+        collection.extend(font);
+    }
+
+    collection
+}
+
+/// A font file of `tables` sorted by tag, each starting at a multiple of 4.
+fn assemble(mut tables: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+    tables.sort_by_key(|(tag, _)| *tag);
+    let mut font = Vec::new();
+    font.extend(0x10000u32.to_be_bytes());
+    for value in [tables.len() as u16, 64, 2, tables.len() as u16 * 16 - 64] {
+        font.extend(value.to_be_bytes());
+    }
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, data) in tables.iter() {
+        font.extend(tag);
+        font.extend(0u32.to_be_bytes());
+        font.extend((offset as u32).to_be_bytes());
+        font.extend((data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in tables.iter() {
+        font.extend(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+
+    font
+}
+
+/// The tables of a font file, not of a collection.
+fn read_tables(font: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let read_u32 = |at: usize| u32::from_be_bytes(font[at..at + 4].try_into().unwrap()) as usize;
+    let table_count = u16::from_be_bytes([font[4], font[5]]) as usize;
+    (0..table_count)
+        .map(|record| {
+            let start = 12 + 16 * record;
+            let offset = read_u32(start + 8);
+            (
+                font[start..start + 4].try_into().unwrap(),
+                &font[offset..offset + read_u32(start + 12)],
+            )
+        })
+        .collect()
+}
+
+fn with_table(font: &[u8], tag: [u8; 4], data: Vec<u8>) -> Vec<u8> {
+    let mut tables: Vec<_> = read_tables(font)
+        .into_iter()
+        .map(|(tag, data)| (tag, data.to_vec()))
+        .collect();
+    tables.push((tag, data));
+    assemble(tables)
+}
+
+fn glyph_outline(contours: &[Vec<[i16; 2]>]) -> Vec<u8> {
+    let points: Vec<[i16; 2]> = contours.iter().flatten().copied().collect();
+    let (min, max) = points
+        .iter()
+        .fold(([i16::MAX; 2], [i16::MIN; 2]), |(min, max), point| {
+            (
+                [min[0].min(point[0]), min[1].min(point[1])],
+                [max[0].max(point[0]), max[1].max(point[1])],
+            )
+        });
+    let mut outline = Vec::new();
+    for value in [contours.len() as i16, min[0], min[1], max[0], max[1]] {
+        outline.extend(value.to_be_bytes());
+    }
+    let mut end = 0;
+    for contour in contours {
+        end += contour.len();
+        outline.extend((end as u16 - 1).to_be_bytes());
+    }
+    // no instructions, every point on the curve, coordinates as 16 bit deltas
+    outline.extend(0u16.to_be_bytes());
+    outline.extend(points.iter().map(|_| 1u8));
+    let mut previous = [0i16, 0];
+    let mut deltas = [Vec::new(), Vec::new()];
+    for point in points.iter() {
+        for axis in 0..2 {
+            deltas[axis].extend((point[axis] - previous[axis]).to_be_bytes());
+        }
+        previous = *point;
+    }
+    outline.extend(deltas.concat());
+    outline.resize(outline.len().next_multiple_of(2), 0);
+
+    outline
+}
+
+/// A `liga` feature with a ligature set per first component.
+fn gsub(ligatures: &[(Vec<u16>, u16)]) -> Vec<u8> {
+    let mut gsub = Vec::new();
+    // header, then the script, feature and lookup lists
+    for value in [1u16, 0, 10, 30, 44] {
+        gsub.extend(value.to_be_bytes());
+    }
+    gsub.extend(1u16.to_be_bytes());
+    gsub.extend(b"DFLT");
+    for value in [8u16, 4, 0, 0, 0xFFFF, 1, 0] {
+        gsub.extend(value.to_be_bytes());
+    }
+    gsub.extend(1u16.to_be_bytes());
+    gsub.extend(b"liga");
+    for value in [8u16, 0, 1, 0] {
+        gsub.extend(value.to_be_bytes());
+    }
+    for value in [1u16, 4, 4, 0, 1, 8] {
+        gsub.extend(value.to_be_bytes());
+    }
+
+    // ligature substitution subtable, its coverage and ligature sets follow
+    // the offsets
+    let count = ligatures.len() as u16;
+    let coverage_offset = 6 + 2 * count;
+    let mut subtable = Vec::new();
+    for value in [1, coverage_offset, count] {
+        subtable.extend(value.to_be_bytes());
+    }
+    let mut set_offset = coverage_offset + 4 + 2 * count;
+    for (components, _) in ligatures {
+        subtable.extend(set_offset.to_be_bytes());
+        set_offset += 8 + 2 * (components.len() as u16 - 1);
+    }
+    subtable.extend(1u16.to_be_bytes());
+    subtable.extend(count.to_be_bytes());
+    for (components, _) in ligatures {
+        subtable.extend(components[0].to_be_bytes());
+    }
+    for (components, ligature) in ligatures {
+        for value in [1u16, 4, *ligature, components.len() as u16] {
+            subtable.extend(value.to_be_bytes());
+        }
+        subtable.extend(components[1..].iter().flat_map(|id| id.to_be_bytes()));
+    }
+    gsub.extend(subtable);
+
+    gsub
+}
+
+/// A legacy `kern` table with a single horizontal subtable of sorted pairs.
+fn kern(pairs: &[(u16, u16, i16)]) -> Vec<u8> {
+    let count = pairs.len() as u16;
+    let mut kern = Vec::new();
+    for value in [0u16, 1, 0, 14 + 6 * count, 1, count, 0, 0, 0] {
+        kern.extend(value.to_be_bytes());
+    }
+    for (left, right, value) in pairs {
+        kern.extend(left.to_be_bytes());
+        kern.extend(right.to_be_bytes());
+        kern.extend(value.to_be_bytes());
+    }
+
+    kern
+}
@@ -0,0 +1,1300 @@
+#[cfg(not(feature = "shaping"))]
+use owned_ttf_parser::{
+    gpos::{PairAdjustment, PositioningSubtable},
+    Tag,
+};
+use std::ops::Range;
+
+use owned_ttf_parser::{Face, GlyphId};
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PositionedGlyph {
+    /// Index of the glyph's face in the fallback chain
+    pub font_index: u32,
+    pub glyph_id: u32,
+    /// Bottom left corner of the glyph's bounding box in pixels, relative to
+    /// the left of the layout box on the first baseline, y pointing down
+    pub origin: [f32; 2],
+    /// Width and height of the glyph's bounding box, in pixels
+    pub size: [f32; 2],
+    pub pixels_per_em: f32,
+    /// Byte index in the laid out string of the first character the glyph
+    /// comes from
+    pub cluster: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextAlignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+    /// Stretches the spaces of wrapped lines to fill the max width, the last
+    /// line of a paragraph stays left aligned
+    Justified,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LayoutOptions {
+    /// Lines wider than this are wrapped at line break opportunities, in
+    /// pixels
+    pub max_width: Option<f32>,
+    pub alignment: TextAlignment,
+    /// Multiplier of the face's line height
+    pub line_spacing: f32,
+    /// Drawn in place of characters missing from the face, `.notdef` is drawn
+    /// when this is `None` or missing too
+    pub replacement_char: Option<char>,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        LayoutOptions {
+            max_width: None,
+            alignment: TextAlignment::Left,
+            line_spacing: 1.0,
+            replacement_char: None,
+        }
+    }
+}
+
+/// Overrides the font size or the font of a byte range of the laid out
+/// string, later runs win where they overlap.
+#[derive(Debug, Clone, Default)]
+pub struct StyleRun {
+    pub range: Range<usize>,
+    /// In pixels
+    pub font_size: Option<f32>,
+    /// Face of the fallback chain tried first, e.g. a bold instance, the
+    /// chain is searched from its start after it
+    pub font_index: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LineLayout {
+    /// Baseline relative to the first one, in pixels
+    pub baseline: f32,
+    /// Distance from the baseline to the top of the line box, the largest
+    /// of the fonts on the line, in pixels
+    pub ascent: f32,
+    /// Distance from the baseline to the bottom of the line box, in pixels,
+    /// negative below the baseline
+    pub descent: f32,
+    /// Start of the line after alignment, in pixels
+    pub left: f32,
+    /// Width without trailing whitespace, in pixels
+    pub width: f32,
+    /// Bytes of the laid out string on this line, without the line break
+    pub text_range: Range<usize>,
+    /// Glyphs of this line in [`TextLayout::glyphs`]
+    pub glyph_range: Range<usize>,
+    /// Clusters of this line in logical order, without trailing whitespace
+    pub clusters: Vec<ClusterLayout>,
+}
+
+/// Horizontal extent of the glyphs of a cluster, including those without
+/// outline, relative to the start of the line, in pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct ClusterLayout {
+    /// Byte index in the laid out string of the first character of the
+    /// cluster, the cluster ends where the next one starts
+    pub start: usize,
+    pub left: f32,
+    pub right: f32,
+    /// Right to left clusters start on their right edge
+    pub rtl: bool,
+}
+
+/// Position of the caret before a character, relative to the top left
+/// corner of the layout box, y pointing down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaretRect {
+    /// Index in [`TextLayout::lines`]
+    pub line: usize,
+    pub x: f32,
+    /// Top of the line box
+    pub top: f32,
+    /// Bottom of the line box
+    pub bottom: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    pub lines: Vec<LineLayout>,
+    /// Width of the layout box, the max width if any or the widest line, in
+    /// pixels
+    pub width: f32,
+    /// Distance from the top of the first line box to the bottom of the last
+    /// one, in pixels
+    pub height: f32,
+    /// Distance from the first baseline to the top of the first line box,
+    /// in pixels
+    pub ascent: f32,
+    /// Distance from the last baseline to the bottom of the last line box,
+    /// in pixels, negative below the baseline
+    pub descent: f32,
+    /// Characters no face has a glyph for, in order of first appearance
+    pub missing_chars: Vec<char>,
+}
+
+impl TextLayout {
+    /// Size of the layout and boxes of its glyphs, to place things around the
+    /// text before drawing it.
+    pub fn metrics(&self) -> TextMetrics {
+        TextMetrics {
+            width: self.width,
+            height: self.height,
+            advance_width: self.lines.iter().map(|line| line.width).fold(0.0, f32::max),
+            ascent: self.ascent,
+            descent: self.descent,
+            line_count: self.lines.len(),
+            glyph_rects: self
+                .glyphs
+                .iter()
+                .map(|glyph| GlyphRect {
+                    cluster: glyph.cluster,
+                    min: [
+                        glyph.origin[0],
+                        self.ascent + glyph.origin[1] - glyph.size[1],
+                    ],
+                    max: [
+                        glyph.origin[0] + glyph.size[0],
+                        self.ascent + glyph.origin[1],
+                    ],
+                })
+                .collect(),
+        }
+    }
+
+    /// Caret before byte `index` of `s`, the laid out string. An index where
+    /// a line wraps is at the start of the next line, indices inside a
+    /// cluster of several graphemes, like a ligature, split it evenly.
+    pub fn caret(&self, s: &str, index: usize) -> CaretRect {
+        if self.lines.is_empty() {
+            return CaretRect {
+                line: 0,
+                x: 0.0,
+                top: 0.0,
+                bottom: 0.0,
+            };
+        }
+        let line_index = self
+            .lines
+            .iter()
+            .rposition(|line| line.text_range.start <= index)
+            .unwrap_or(0);
+        let line = &self.lines[line_index];
+        let baseline = self.ascent + line.baseline;
+        CaretRect {
+            line: line_index,
+            x: line.left + caret_offset(s, line, index.min(s.len())),
+            top: baseline - line.ascent,
+            bottom: baseline - line.descent,
+        }
+    }
+
+    /// Byte index of `s`, the laid out string, of the grapheme boundary
+    /// nearest to `point`, relative to the top left corner of the layout box.
+    /// Points above or below the text hit the first or last line.
+    pub fn hit_test(&self, s: &str, point: [f32; 2]) -> usize {
+        if self.lines.is_empty() {
+            return 0;
+        }
+        let line_index = self
+            .lines
+            .iter()
+            .position(|line| point[1] < self.ascent + line.baseline - line.descent)
+            .unwrap_or(self.lines.len() - 1);
+        let line = &self.lines[line_index];
+        // a wrapped line ends before its trailing whitespace, the boundary
+        // after it belongs to the next line
+        let wrapped = self
+            .lines
+            .get(line_index + 1)
+            .is_some_and(|next_line| next_line.text_range.start == line.text_range.end);
+        let end = match wrapped {
+            true => line.text_range.start + s[line.text_range.clone()].trim_end().len(),
+            false => line.text_range.end,
+        };
+        s[line.text_range.start..end]
+            .grapheme_indices(true)
+            .map(|(offset, _)| line.text_range.start + offset)
+            .chain(std::iter::once(end))
+            .fold((line.text_range.start, f32::INFINITY), |nearest, index| {
+                let distance = (line.left + caret_offset(s, line, index) - point[0]).abs();
+                // later boundaries win ties, e.g. after trailing whitespace
+                match distance <= nearest.1 {
+                    true => (index, distance),
+                    false => nearest,
+                }
+            })
+            .0
+    }
+}
+
+/// Caret position before byte `index` of `s` on `line`, relative to the
+/// start of the line.
+fn caret_offset(s: &str, line: &LineLayout, index: usize) -> f32 {
+    let Some(position) = line
+        .clusters
+        .iter()
+        .rposition(|cluster| cluster.start <= index)
+        .or((!line.clusters.is_empty()).then_some(0))
+    else {
+        return 0.0;
+    };
+    let cluster = &line.clusters[position];
+    let cluster_end = match line.clusters.get(position + 1) {
+        Some(next_cluster) => next_cluster.start,
+        None => line.text_range.start + s[line.text_range.clone()].trim_end().len(),
+    };
+    let fraction = if index <= cluster.start {
+        0.0
+    } else if index >= cluster_end {
+        1.0
+    } else {
+        s[cluster.start..index].graphemes(true).count() as f32
+            / s[cluster.start..cluster_end].graphemes(true).count() as f32
+    };
+    match cluster.rtl {
+        true => cluster.right - (cluster.right - cluster.left) * fraction,
+        false => cluster.left + (cluster.right - cluster.left) * fraction,
+    }
+}
+
+/// Bounding box of a laid out glyph, relative to the top left corner of the
+/// layout box, y pointing down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphRect {
+    /// Byte index in the laid out string of the first character the glyph
+    /// comes from
+    pub cluster: usize,
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+/// Measurements of a laid out string, see [`TextLayout::metrics`].
+#[derive(Debug, Clone, Default)]
+pub struct TextMetrics {
+    /// Width of the layout box, the max width if any or the widest line
+    pub width: f32,
+    /// Distance from the top of the first line box to the bottom of the last
+    /// one
+    pub height: f32,
+    /// Width of the widest line without trailing whitespace
+    pub advance_width: f32,
+    /// Distance from the first baseline to the top of the layout box
+    pub ascent: f32,
+    /// Distance from the last baseline to the bottom of the layout box,
+    /// negative below the baseline
+    pub descent: f32,
+    pub line_count: usize,
+    /// Glyphs with an outline, in the order they are drawn
+    pub glyph_rects: Vec<GlyphRect>,
+}
+
+impl TextMetrics {
+    /// Multiplies every length by `factor`, e.g. to turn physical pixels
+    /// into logical ones.
+    pub fn scaled(mut self, factor: f32) -> Self {
+        self.width *= factor;
+        self.height *= factor;
+        self.advance_width *= factor;
+        self.ascent *= factor;
+        self.descent *= factor;
+        for rect in self.glyph_rects.iter_mut() {
+            rect.min = rect.min.map(|x| x * factor);
+            rect.max = rect.max.map(|x| x * factor);
+        }
+        self
+    }
+}
+
+/// A glyph of a shaped run, in font units.
+#[derive(Debug, Clone, Copy)]
+struct ShapedGlyph {
+    font_index: u32,
+    glyph_id: u32,
+    /// Byte index of the first character this glyph comes from
+    cluster: usize,
+    /// In pixels
+    font_size: f32,
+    rtl: bool,
+    x_advance: i32,
+    x_offset: i32,
+    y_offset: i32,
+}
+
+/// Font size and preferred face of every byte of the laid out string.
+struct Styles<'a> {
+    font_size: f32,
+    runs: &'a [StyleRun],
+}
+
+impl Styles<'_> {
+    fn font_size(&self, index: usize) -> f32 {
+        self.runs
+            .iter()
+            .rev()
+            .filter(|run| run.range.contains(&index))
+            .find_map(|run| run.font_size)
+            .unwrap_or(self.font_size)
+    }
+
+    fn font_index(&self, index: usize) -> usize {
+        self.runs
+            .iter()
+            .rev()
+            .filter(|run| run.range.contains(&index))
+            .find_map(|run| run.font_index)
+            .unwrap_or(0)
+    }
+}
+
+/// Ascent, descent and line gap of a face at a font size, in pixels.
+fn line_metrics(face: &Face, font_size: f32) -> [f32; 3] {
+    let scale = font_size / face.units_per_em() as f32;
+    [
+        face.ascender() as f32 * scale,
+        face.descender() as f32 * scale,
+        face.line_gap() as f32 * scale,
+    ]
+}
+
+/// Lays `s` out in lines, breaking at newlines and, when `options` has a max
+/// width, at line break opportunities. Each character is drawn with the first
+/// face of `faces` that has a glyph for it.
+pub fn layout_text(
+    faces: &[&Face],
+    s: &str,
+    font_size: f32,
+    options: &LayoutOptions,
+) -> TextLayout {
+    layout_rich_text(faces, s, font_size, &[], options)
+}
+
+/// Same as [`layout_text`] with parts of `s` in other sizes or fonts. Glyphs
+/// of a line share its baseline, lines are as tall as their largest font.
+pub fn layout_rich_text(
+    faces: &[&Face],
+    s: &str,
+    font_size: f32,
+    runs: &[StyleRun],
+    options: &LayoutOptions,
+) -> TextLayout {
+    let styles = Styles { font_size, runs };
+    let mut layout = TextLayout::default();
+    for (index, this_char) in s.char_indices() {
+        if !this_char.is_control()
+            && find_font(faces, this_char, styles.font_index(index)).is_none()
+            && !layout.missing_chars.contains(&this_char)
+        {
+            layout.missing_chars.push(this_char);
+        }
+    }
+
+    let mut paragraph_start = 0;
+    for raw_paragraph in s.split('\n') {
+        let paragraph = raw_paragraph.strip_suffix('\r').unwrap_or(raw_paragraph);
+        let line_ranges = match options.max_width {
+            Some(max_width) => wrap_paragraph(
+                faces,
+                s,
+                paragraph_start..paragraph_start + paragraph.len(),
+                &styles,
+                max_width,
+                options,
+            ),
+            None => std::iter::once(paragraph_start..paragraph_start + paragraph.len()).collect(),
+        };
+        let line_count = line_ranges.len();
+        for (index, text_range) in line_ranges.into_iter().enumerate() {
+            let justify = options.alignment == TextAlignment::Justified && index + 1 < line_count;
+            layout_line(faces, s, text_range, &styles, justify, options, &mut layout);
+        }
+        paragraph_start += raw_paragraph.len() + 1;
+    }
+
+    layout.width = layout
+        .lines
+        .iter()
+        .fold(0.0, |width: f32, line| width.max(line.width));
+    if let Some(max_width) = options.max_width {
+        layout.width = layout.width.max(max_width);
+    }
+    // align lines in the layout box
+    for line in layout.lines.iter_mut() {
+        let shift = match options.alignment {
+            TextAlignment::Left | TextAlignment::Justified => 0.0,
+            TextAlignment::Center => (layout.width - line.width) / 2.0,
+            TextAlignment::Right => layout.width - line.width,
+        };
+        line.left += shift;
+        for glyph in layout.glyphs[line.glyph_range.clone()].iter_mut() {
+            glyph.origin[0] += shift;
+        }
+    }
+    let first_line = layout.lines.first().unwrap();
+    let last_line = layout.lines.last().unwrap();
+    layout.ascent = first_line.ascent;
+    layout.descent = last_line.descent;
+    layout.height = layout.ascent + last_line.baseline - layout.descent;
+
+    layout
+}
+
+/// Greedily splits the paragraph at `range` of `s` into lines no wider than
+/// `max_width` pixels, breaking inside words only when a single word doesn't
+/// fit.
+fn wrap_paragraph(
+    faces: &[&Face],
+    s: &str,
+    range: Range<usize>,
+    styles: &Styles,
+    max_width: f32,
+    options: &LayoutOptions,
+) -> Vec<Range<usize>> {
+    let paragraph_start = range.start;
+    let paragraph = &s[range.clone()];
+    // advance of every character of the paragraph, glyphs are attributed to
+    // the first character of their cluster
+    let mut char_advances = vec![0.0; paragraph.len()];
+    for glyph in shape(faces, s, range, styles, options) {
+        char_advances[glyph.cluster - paragraph_start] +=
+            glyph.x_advance as f32 * font_scale(faces, &glyph);
+    }
+    let width_of = |range: Range<usize>| -> f32 {
+        let trimmed_end = range.start + paragraph[range.clone()].trim_end().len();
+        char_advances[range.start..trimmed_end].iter().sum()
+    };
+
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    let mut segment_start = 0;
+    for (segment_end, _) in unicode_linebreak::linebreaks(paragraph) {
+        if width_of(line_start..segment_end) > max_width {
+            if line_start < segment_start {
+                lines.push(line_start..segment_start);
+                line_start = segment_start;
+            }
+            // a single word longer than the line, break between characters
+            while width_of(line_start..segment_end) > max_width {
+                let mut end = line_start;
+                for (offset, this_char) in paragraph[line_start..segment_end].char_indices() {
+                    let next = line_start + offset + this_char.len_utf8();
+                    if end > line_start && width_of(line_start..next) > max_width {
+                        break;
+                    }
+                    end = next;
+                }
+                if end >= segment_end {
+                    break;
+                }
+                lines.push(line_start..end);
+                line_start = end;
+            }
+        }
+        segment_start = segment_end;
+    }
+    lines.push(line_start..paragraph.len());
+
+    lines
+        .into_iter()
+        .map(|line| paragraph_start + line.start..paragraph_start + line.end)
+        .collect()
+}
+
+fn layout_line(
+    faces: &[&Face],
+    s: &str,
+    text_range: Range<usize>,
+    styles: &Styles,
+    justify: bool,
+    options: &LayoutOptions,
+    layout: &mut TextLayout,
+) {
+    let line_end = text_range.start + s[text_range.clone()].trim_end().len();
+    let glyphs = shape(faces, s, text_range.start..line_end, styles, options);
+    let natural_width = glyphs
+        .iter()
+        .map(|glyph| glyph.x_advance as f32 * font_scale(faces, glyph))
+        .sum::<f32>();
+    let is_space = |glyph: &ShapedGlyph| s[glyph.cluster..].starts_with(' ');
+    let mut space_extra = 0.0;
+    if let (true, Some(max_width)) = (justify, options.max_width) {
+        let spaces = glyphs.iter().filter(|glyph| is_space(glyph)).count();
+        if spaces > 0 {
+            space_extra = (max_width - natural_width).max(0.0) / spaces as f32;
+        }
+    }
+
+    // the line box holds the largest of the fonts its glyphs come from,
+    // fallback fonts included, empty lines get the font of where they start
+    let [ascent, descent, line_gap] = glyphs
+        .iter()
+        .map(|glyph| line_metrics(faces[glyph.font_index as usize], glyph.font_size))
+        .reduce(
+            |[ascent, descent, line_gap], [glyph_ascent, glyph_descent, glyph_line_gap]| {
+                [
+                    ascent.max(glyph_ascent),
+                    descent.min(glyph_descent),
+                    line_gap.max(glyph_line_gap),
+                ]
+            },
+        )
+        .unwrap_or_else(|| {
+            line_metrics(
+                faces[styles.font_index(text_range.start).min(faces.len() - 1)],
+                styles.font_size(text_range.start),
+            )
+        });
+    let baseline = match layout.lines.last() {
+        Some(last_line) => {
+            last_line.baseline + (ascent - last_line.descent + line_gap) * options.line_spacing
+        }
+        None => 0.0,
+    };
+
+    let glyph_start = layout.glyphs.len();
+    let mut clusters: Vec<ClusterLayout> = Vec::new();
+    let mut pen_x = 0.0;
+    for glyph in glyphs.iter() {
+        let face = faces[glyph.font_index as usize];
+        let scale = font_scale(faces, glyph);
+        // glyphs without outline, like spaces, only move the pen
+        if let Some(info) = face.glyph_bounding_box(GlyphId(glyph.glyph_id as u16)) {
+            layout.glyphs.push(PositionedGlyph {
+                font_index: glyph.font_index,
+                glyph_id: glyph.glyph_id,
+                origin: [
+                    pen_x + (glyph.x_offset + info.x_min as i32) as f32 * scale,
+                    baseline - (glyph.y_offset + info.y_min as i32) as f32 * scale,
+                ],
+                size: [
+                    (info.x_max - info.x_min) as f32 * scale,
+                    (info.y_max - info.y_min) as f32 * scale,
+                ],
+                pixels_per_em: glyph.font_size,
+                cluster: glyph.cluster,
+            });
+        }
+        let left = pen_x;
+        pen_x += glyph.x_advance as f32 * scale;
+        if is_space(glyph) {
+            pen_x += space_extra;
+        }
+        match clusters
+            .iter_mut()
+            .find(|cluster| cluster.start == glyph.cluster)
+        {
+            Some(cluster) => {
+                cluster.left = cluster.left.min(left);
+                cluster.right = cluster.right.max(pen_x);
+            }
+            None => clusters.push(ClusterLayout {
+                start: glyph.cluster,
+                left,
+                right: pen_x,
+                rtl: glyph.rtl,
+            }),
+        }
+    }
+    clusters.sort_by_key(|cluster| cluster.start);
+
+    layout.lines.push(LineLayout {
+        baseline,
+        ascent,
+        descent,
+        left: 0.0,
+        width: pen_x,
+        text_range,
+        glyph_range: glyph_start..layout.glyphs.len(),
+        clusters,
+    });
+}
+
+/// Pixels per font unit of the glyph's face.
+#[inline]
+fn font_scale(faces: &[&Face], glyph: &ShapedGlyph) -> f32 {
+    glyph.font_size / faces[glyph.font_index as usize].units_per_em() as f32
+}
+
+/// Index of the face that has a glyph for `c`, trying `preferred` before the
+/// fallback chain.
+fn find_font(faces: &[&Face], c: char, preferred: usize) -> Option<usize> {
+    if faces
+        .get(preferred)
+        .is_some_and(|face| face.glyph_index(c).is_some())
+    {
+        return Some(preferred);
+    }
+    faces.iter().position(|face| face.glyph_index(c).is_some())
+}
+
+/// Splits `range` of `s` into runs of characters drawn with the same face.
+/// Whitespace and characters no face has stay in the current run so they
+/// don't break shaping.
+fn font_runs(
+    faces: &[&Face],
+    s: &str,
+    range: Range<usize>,
+    styles: &Styles,
+) -> Vec<(Range<usize>, usize)> {
+    let mut runs: Vec<(Range<usize>, usize)> = Vec::new();
+    for (offset, this_char) in s[range.clone()].char_indices() {
+        let start = range.start + offset;
+        let end = start + this_char.len_utf8();
+        let current = runs.last().map(|(_, font_index)| *font_index);
+        let found = find_font(faces, this_char, styles.font_index(start));
+        let font_index = match (current, found) {
+            (Some(current), _) if this_char.is_whitespace() => current,
+            (Some(current), None) => current,
+            (_, Some(font_index)) => font_index,
+            (None, None) => 0,
+        };
+        match runs.last_mut() {
+            Some((run, index)) if *index == font_index => run.end = end,
+            _ => runs.push((start..end, font_index)),
+        }
+    }
+
+    runs
+}
+
+/// Shapes `range` of `s` run by run through the fallback chain, glyphs come
+/// out in visual order with clusters indexing `s`. The `.notdef` glyphs of
+/// missing characters are swapped for the replacement character's glyph if
+/// a face has one.
+fn shape(
+    faces: &[&Face],
+    s: &str,
+    range: Range<usize>,
+    styles: &Styles,
+    options: &LayoutOptions,
+) -> Vec<ShapedGlyph> {
+    let mut glyphs = Vec::new();
+    for (direction_run, rtl) in directional_runs(&s[range.clone()]) {
+        let direction_run = range.start + direction_run.start..range.start + direction_run.end;
+        let mut runs = font_runs(faces, s, direction_run, styles);
+        if rtl {
+            runs.reverse();
+        }
+        for (run, font_index) in runs {
+            glyphs.extend(
+                shape_run(faces[font_index], &s[run.clone()], rtl)
+                    .into_iter()
+                    .map(|glyph| ShapedGlyph {
+                        font_index: font_index as u32,
+                        cluster: run.start + glyph.cluster,
+                        font_size: styles.font_size(run.start + glyph.cluster),
+                        rtl,
+                        ..glyph
+                    }),
+            );
+        }
+    }
+    // control characters the faces don't map draw nothing
+    glyphs.retain(|glyph| glyph.glyph_id != 0 || !s[glyph.cluster..].starts_with(char::is_control));
+    let Some((font_index, replacement)) = options.replacement_char.and_then(|c| {
+        faces
+            .iter()
+            .enumerate()
+            .find_map(|(font_index, face)| Some((font_index, face.glyph_index(c)?)))
+    }) else {
+        return glyphs;
+    };
+    let advance = faces[font_index]
+        .glyph_hor_advance(replacement)
+        .unwrap_or(0) as i32;
+    for glyph in glyphs.iter_mut().filter(|glyph| glyph.glyph_id == 0) {
+        glyph.font_index = font_index as u32;
+        glyph.glyph_id = replacement.0 as u32;
+        glyph.x_advance = advance;
+        glyph.x_offset = 0;
+        glyph.y_offset = 0;
+    }
+
+    glyphs
+}
+
+/// Runs of `s` with a single direction in visual order, and whether they are
+/// right to left.
+#[cfg(feature = "shaping")]
+fn directional_runs(s: &str) -> Vec<(Range<usize>, bool)> {
+    let bidi_info = unicode_bidi::BidiInfo::new(s, None);
+    let mut runs = Vec::new();
+    for paragraph in bidi_info.paragraphs.iter() {
+        let (levels, visual_runs) = bidi_info.visual_runs(paragraph, paragraph.range.clone());
+        runs.extend(visual_runs.into_iter().map(|run| {
+            let rtl = levels[run.start].is_rtl();
+            (run, rtl)
+        }));
+    }
+
+    runs
+}
+
+/// Without shaping text is always laid out left to right.
+#[cfg(not(feature = "shaping"))]
+fn directional_runs(s: &str) -> Vec<(Range<usize>, bool)> {
+    vec![(0..s.len(), false)]
+}
+
+/// Shapes a single direction run of `s` with rustybuzz, which handles
+/// ligatures, mark positioning and complex scripts.
+#[cfg(feature = "shaping")]
+fn shape_run(face: &Face, s: &str, rtl: bool) -> Vec<ShapedGlyph> {
+    let shaper = rustybuzz::Face::from_face(face.clone());
+    let mut buffer = rustybuzz::UnicodeBuffer::new();
+    buffer.push_str(s);
+    buffer.set_direction(if rtl {
+        rustybuzz::Direction::RightToLeft
+    } else {
+        rustybuzz::Direction::LeftToRight
+    });
+    let glyph_buffer = rustybuzz::shape(&shaper, &[], buffer);
+
+    glyph_buffer
+        .glyph_infos()
+        .iter()
+        .zip(glyph_buffer.glyph_positions())
+        .map(|(info, position)| ShapedGlyph {
+            font_index: 0,
+            glyph_id: info.glyph_id,
+            cluster: info.cluster as usize,
+            font_size: 0.0,
+            rtl: false,
+            x_advance: position.x_advance,
+            x_offset: position.x_offset,
+            y_offset: position.y_offset,
+        })
+        .collect()
+}
+
+/// One glyph per character using the face's horizontal advances, side
+/// bearings and pair kerning.
+#[cfg(not(feature = "shaping"))]
+fn shape_run(face: &Face, s: &str, _rtl: bool) -> Vec<ShapedGlyph> {
+    let kerning = PairKerning::new(face);
+    let mut glyphs: Vec<ShapedGlyph> = Vec::new();
+    let mut last_glyph = None;
+    for (cluster, this_char) in s.char_indices() {
+        // missing characters fall back to `.notdef`
+        let glyph_index = face.glyph_index(this_char).unwrap_or(GlyphId(0));
+        if let (Some(last_glyph), Some(last)) = (last_glyph, glyphs.last_mut()) {
+            last.x_advance += kerning.get(face, last_glyph, glyph_index) as i32;
+        }
+        glyphs.push(ShapedGlyph {
+            font_index: 0,
+            glyph_id: glyph_index.0 as u32,
+            cluster,
+            font_size: 0.0,
+            rtl: false,
+            x_advance: face.glyph_hor_advance(glyph_index).unwrap_or(0) as i32,
+            x_offset: 0,
+            y_offset: 0,
+        });
+        last_glyph = Some(glyph_index);
+    }
+
+    glyphs
+}
+
+/// Pair adjustments from the `kern` feature of `GPOS`, or from the legacy
+/// `kern` table for fonts without one.
+#[cfg(not(feature = "shaping"))]
+struct PairKerning {
+    gpos_lookups: Vec<u16>,
+}
+
+#[cfg(not(feature = "shaping"))]
+impl PairKerning {
+    fn new(face: &Face) -> Self {
+        let mut gpos_lookups = Vec::new();
+        if let Some(gpos) = face.tables().gpos {
+            for feature in gpos.features {
+                if feature.tag != Tag::from_bytes(b"kern") {
+                    continue;
+                }
+                for lookup_index in feature.lookup_indices {
+                    if !gpos_lookups.contains(&lookup_index) {
+                        gpos_lookups.push(lookup_index);
+                    }
+                }
+            }
+        }
+
+        PairKerning { gpos_lookups }
+    }
+
+    /// Adjustment of the pen between `left` and `right`, in font units.
+    fn get(&self, face: &Face, left: GlyphId, right: GlyphId) -> i16 {
+        if let Some(gpos) = face.tables().gpos {
+            if !self.gpos_lookups.is_empty() {
+                let mut adjustment = 0;
+                for lookup_index in self.gpos_lookups.iter() {
+                    let Some(lookup) = gpos.lookups.get(*lookup_index) else {
+                        continue;
+                    };
+                    // the first subtable covering the pair wins
+                    if let Some(value) = lookup
+                        .subtables
+                        .into_iter::<PositioningSubtable>()
+                        .find_map(|subtable| match subtable {
+                            PositioningSubtable::Pair(pair) => pair_adjustment(&pair, left, right),
+                            _ => None,
+                        })
+                    {
+                        adjustment += value;
+                    }
+                }
+                return adjustment;
+            }
+        }
+
+        face.tables()
+            .kern
+            .and_then(|kern| {
+                kern.subtables
+                    .into_iter()
+                    .filter(|subtable| {
+                        subtable.horizontal
+                            && !subtable.has_cross_stream
+                            && !subtable.has_state_machine
+                    })
+                    .find_map(|subtable| subtable.glyphs_kerning(left, right))
+            })
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(not(feature = "shaping"))]
+fn pair_adjustment(pair: &PairAdjustment, left: GlyphId, right: GlyphId) -> Option<i16> {
+    let coverage_index = pair.coverage().get(left)?;
+    let (first, _) = match pair {
+        PairAdjustment::Format1 { sets, .. } => sets.get(coverage_index)?.get(right)?,
+        PairAdjustment::Format2 {
+            classes, matrix, ..
+        } => matrix.get((classes.0.get(left), classes.1.get(right)))?,
+    };
+
+    Some(first.x_advance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::font_manager::test_font::{self, eng1, TestGlyph};
+
+    const FONT_SIZE: f32 = 20.0;
+
+    /// Advance of every character of `eng1.ttf`, a monospaced font.
+    fn advance(face: &Face) -> f32 {
+        let advance_of = |c| {
+            face.glyph_hor_advance(face.glyph_index(c).unwrap())
+                .unwrap() as f32
+                * FONT_SIZE
+                / face.units_per_em() as f32
+        };
+        assert_eq!(advance_of('i'), advance_of('W'));
+        advance_of('a')
+    }
+
+    fn lines<'a>(s: &'a str, layout: &TextLayout) -> Vec<&'a str> {
+        layout
+            .lines
+            .iter()
+            .map(|line| &s[line.text_range.clone()])
+            .collect()
+    }
+
+    #[test]
+    fn lines_wrap_greedily_at_spaces() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let s = "aaa bbb ccc dd";
+        let options = LayoutOptions {
+            // "aaa bbb" fits, "aaa bbb ccc" doesn't
+            max_width: Some(advance(&face) * 9.0),
+            ..Default::default()
+        };
+        let layout = layout_text(&[&face], s, FONT_SIZE, &options);
+        assert_eq!(lines(s, &layout), ["aaa bbb ", "ccc dd"]);
+        // trailing spaces don't count towards the width
+        assert_eq!(layout.lines[0].width, advance(&face) * 7.0);
+        assert!(layout.lines[1].baseline > layout.lines[0].baseline);
+        assert_eq!(layout.width, advance(&face) * 9.0);
+    }
+
+    #[test]
+    fn newlines_always_break() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let s = "aa\n\nbb";
+        let layout = layout_text(&[&face], s, FONT_SIZE, &LayoutOptions::default());
+        assert_eq!(lines(s, &layout), ["aa", "", "bb"]);
+    }
+
+    #[test]
+    fn long_words_break_between_characters() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let s = "abcdefghij xy";
+        let options = LayoutOptions {
+            max_width: Some(advance(&face) * 4.0),
+            ..Default::default()
+        };
+        let layout = layout_text(&[&face], s, FONT_SIZE, &options);
+        assert_eq!(lines(s, &layout), ["abcd", "efgh", "ij ", "xy"]);
+        for line in layout.lines.iter() {
+            assert!(line.width <= advance(&face) * 4.0);
+        }
+    }
+
+    #[test]
+    fn justified_lines_reach_the_right_edge() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let s = "aa bb cc dd ee";
+        let max_width = advance(&face) * 10.5;
+        let options = LayoutOptions {
+            max_width: Some(max_width),
+            alignment: TextAlignment::Justified,
+            ..Default::default()
+        };
+        let layout = layout_text(&[&face], s, FONT_SIZE, &options);
+        assert_eq!(lines(s, &layout), ["aa bb cc ", "dd ee"]);
+        // the spaces between the words take up the slack
+        let first_line = &layout.lines[0];
+        assert!((first_line.width - max_width).abs() < 1e-3);
+        let last_glyph = &layout.glyphs[first_line.glyph_range.end - 1];
+        assert_eq!(&s[last_glyph.cluster..last_glyph.cluster + 1], "c");
+        let cluster = first_line.clusters.last().unwrap();
+        assert!((cluster.right - max_width).abs() < 1e-3);
+        // the last line of a paragraph keeps its natural spacing
+        assert_eq!(layout.lines[1].width, advance(&face) * 5.0);
+    }
+
+    #[test]
+    fn lines_are_as_tall_as_their_fallback_fonts() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        // a fallback font taller than eng1.ttf, with a glyph reaching out of
+        // eng1.ttf's line box on both sides
+        let tall_font = test_font::build(
+            1500,
+            -700,
+            &[TestGlyph {
+                c: '中',
+                contours: vec![test_font::rectangle([100, -600], [900, 1400], false)],
+                advance: 1000,
+            }],
+            &[],
+            &[],
+        );
+        let tall_face = Face::parse(&tall_font, 0).unwrap();
+        let s = "ab\na中\nb";
+        let layout = layout_text(&[&face, &tall_face], s, FONT_SIZE, &Default::default());
+        assert_eq!(lines(s, &layout), ["ab", "a中", "b"]);
+
+        let [ascent, descent, _] = line_metrics(&face, FONT_SIZE);
+        assert_eq!(layout.lines[0].ascent, ascent);
+        assert_eq!(layout.lines[0].descent, descent);
+        assert_eq!(layout.lines[1].ascent, 30.0);
+        assert_eq!(layout.lines[1].descent, -14.0);
+        assert_eq!(layout.lines[2].ascent, ascent);
+
+        let line = &layout.lines[1];
+        let glyph = layout
+            .glyphs
+            .iter()
+            .find(|glyph| glyph.font_index == 1)
+            .unwrap();
+        assert!(glyph.origin[1] - glyph.size[1] >= line.baseline - line.ascent - 1e-3);
+        assert!(glyph.origin[1] <= line.baseline - line.descent + 1e-3);
+        // neighbouring line boxes don't overlap
+        for pair in layout.lines.windows(2) {
+            assert!(pair[1].baseline - pair[1].ascent >= pair[0].baseline - pair[0].descent - 1e-3);
+        }
+    }
+
+    #[test]
+    fn larger_spans_share_the_baseline_and_grow_the_line() {
+        // squares sitting on the baseline, 0.6 em apart
+        let font_file = test_font::build(
+            800,
+            -200,
+            &[TestGlyph {
+                c: 'a',
+                contours: vec![test_font::rectangle([0, 0], [500, 500], false)],
+                advance: 600,
+            }],
+            &[],
+            &[],
+        );
+        let face = Face::parse(&font_file, 0).unwrap();
+        let runs = [StyleRun {
+            range: 1..2,
+            font_size: Some(2.0 * FONT_SIZE),
+            font_index: None,
+        }];
+        let s = "aaa\na";
+        let layout = layout_rich_text(&[&face], s, FONT_SIZE, &runs, &Default::default());
+        assert_eq!(lines(s, &layout), ["aaa", "a"]);
+
+        let line = &layout.lines[0];
+        let glyphs = &layout.glyphs[line.glyph_range.clone()];
+        let sizes: Vec<_> = glyphs.iter().map(|glyph| glyph.size[1]).collect();
+        assert_eq!(sizes, [10.0, 20.0, 10.0]);
+        for glyph in glyphs {
+            assert!((glyph.origin[1] - line.baseline).abs() < 1e-3);
+        }
+        let x: Vec<_> = glyphs.iter().map(|glyph| glyph.origin[0]).collect();
+        assert_eq!(x, [0.0, 12.0, 36.0]);
+        // the line box is the one of the 40px span, the next line is 20px
+        assert_eq!([line.ascent, line.descent], [32.0, -8.0]);
+        assert_eq!(layout.ascent, 32.0);
+        let next_line = &layout.lines[1];
+        assert_eq!([next_line.ascent, next_line.descent], [16.0, -4.0]);
+        assert_eq!(
+            next_line.baseline,
+            line.baseline - line.descent + next_line.ascent
+        );
+        assert_eq!(layout.height, 32.0 + 8.0 + 16.0 + 4.0);
+    }
+
+    /// A font with an "fi" ligature and Hebrew letters, every glyph one em
+    /// wide.
+    fn ligature_font() -> Vec<u8> {
+        let glyph = |c| TestGlyph {
+            c,
+            contours: vec![test_font::rectangle([100, 0], [900, 700], false)],
+            advance: 1000,
+        };
+        test_font::build(
+            800,
+            -200,
+            &['f', 'i', 'א', 'ב', '\u{E000}'].map(glyph),
+            &[("fi", '\u{E000}')],
+            &[],
+        )
+    }
+
+    #[test]
+    fn carets_follow_wrapped_lines() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let advance = advance(&face);
+        let s = "aaa bbb ccc";
+        let options = LayoutOptions {
+            max_width: Some(advance * 9.0),
+            ..Default::default()
+        };
+        let layout = layout_text(&[&face], s, FONT_SIZE, &options);
+        assert_eq!(lines(s, &layout), ["aaa bbb ", "ccc"]);
+
+        let caret = layout.caret(s, 5);
+        assert_eq!((caret.line, caret.x), (0, advance * 5.0));
+        // the start of a wrapped line is on that line, not after the
+        // trailing space of the one before
+        let caret = layout.caret(s, 8);
+        assert_eq!((caret.line, caret.x), (1, 0.0));
+        assert_eq!(caret.top, layout.caret(s, 5).bottom);
+        let caret = layout.caret(s, s.len());
+        assert_eq!((caret.line, caret.x), (1, advance * 3.0));
+
+        let line_middle = |caret: CaretRect| (caret.top + caret.bottom) / 2.0;
+        let first_line = line_middle(layout.caret(s, 0));
+        let second_line = line_middle(layout.caret(s, 8));
+        assert_eq!(layout.hit_test(s, [advance * 1.4, first_line]), 1);
+        assert_eq!(layout.hit_test(s, [advance * 1.6, second_line]), 10);
+        // past the end of a wrapped line hits before its trailing
+        // whitespace, the boundary after it belongs to the next line
+        assert_eq!(layout.hit_test(s, [advance * 8.0, first_line]), 7);
+        assert_eq!(layout.hit_test(s, [advance * 20.0, first_line]), 7);
+        assert_eq!(layout.hit_test(s, [advance * 20.0, second_line]), s.len());
+    }
+
+    #[test]
+    fn points_outside_the_text_hit_the_nearest_line() {
+        let font_file = eng1();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let advance = advance(&face);
+        let s = "ab\ncd";
+        let layout = layout_text(&[&face], s, FONT_SIZE, &Default::default());
+        assert_eq!(layout.hit_test(s, [advance, -100.0]), 1);
+        assert_eq!(layout.hit_test(s, [-100.0, -100.0]), 0);
+        assert_eq!(layout.hit_test(s, [advance, layout.height + 100.0]), 4);
+        assert_eq!(layout.hit_test(s, [100.0, layout.height + 100.0]), s.len());
+        assert_eq!(TextLayout::default().hit_test("", [0.0, 0.0]), 0);
+        assert_eq!(TextLayout::default().caret("", 0).x, 0.0);
+    }
+
+    #[cfg(feature = "shaping")]
+    #[test]
+    fn carets_split_ligatures_by_grapheme() {
+        let font_file = ligature_font();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let s = "ffi";
+        let layout = layout_text(&[&face], s, FONT_SIZE, &Default::default());
+        assert_eq!(layout.glyphs.len(), 2);
+        assert_eq!(
+            layout.lines[0]
+                .clusters
+                .iter()
+                .map(|cluster| cluster.start)
+                .collect::<Vec<_>>(),
+            [0, 1]
+        );
+
+        let caret_x = |index| layout.caret(s, index).x;
+        assert_eq!(caret_x(1), FONT_SIZE);
+        assert_eq!(caret_x(2), FONT_SIZE * 1.5);
+        assert_eq!(caret_x(3), FONT_SIZE * 2.0);
+        let y = FONT_SIZE / 2.0;
+        assert_eq!(layout.hit_test(s, [FONT_SIZE * 1.4, y]), 2);
+        assert_eq!(layout.hit_test(s, [FONT_SIZE * 1.2, y]), 1);
+        assert_eq!(layout.hit_test(s, [FONT_SIZE * 1.8, y]), 3);
+    }
+
+    #[cfg(feature = "shaping")]
+    #[test]
+    fn carets_run_right_to_left_in_rtl_clusters() {
+        let font_file = ligature_font();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let s = "אבא";
+        let layout = layout_text(&[&face], s, FONT_SIZE, &Default::default());
+        let line = &layout.lines[0];
+        assert!(line.clusters.iter().all(|cluster| cluster.rtl));
+
+        // the first letter is on the right
+        let caret_x = |index| layout.caret(s, index).x - line.left;
+        assert_eq!(caret_x(0), FONT_SIZE * 3.0);
+        assert_eq!(caret_x(2), FONT_SIZE * 2.0);
+        assert_eq!(caret_x(4), FONT_SIZE);
+        assert_eq!(caret_x(s.len()), 0.0);
+        let y = FONT_SIZE / 2.0;
+        assert_eq!(layout.hit_test(s, [line.left + FONT_SIZE * 2.9, y]), 0);
+        assert_eq!(layout.hit_test(s, [line.left + FONT_SIZE * 2.2, y]), 2);
+        assert_eq!(
+            layout.hit_test(s, [line.left + FONT_SIZE * 0.2, y]),
+            s.len()
+        );
+    }
+
+    /// Without shaping there are no ligatures and no reordering, every
+    /// character is a cluster of its own laid out left to right.
+    #[cfg(not(feature = "shaping"))]
+    #[test]
+    fn carets_follow_characters_without_shaping() {
+        let font_file = ligature_font();
+        let face = Face::parse(&font_file, 0).unwrap();
+        let y = FONT_SIZE / 2.0;
+        for s in ["ffi", "אבא"] {
+            let layout = layout_text(&[&face], s, FONT_SIZE, &Default::default());
+            let line = &layout.lines[0];
+            let starts: Vec<usize> = s.char_indices().map(|(index, _)| index).collect();
+            assert_eq!(layout.glyphs.len(), starts.len());
+            assert_eq!(
+                line.clusters
+                    .iter()
+                    .map(|cluster| cluster.start)
+                    .collect::<Vec<_>>(),
+                starts
+            );
+            assert!(line.clusters.iter().all(|cluster| !cluster.rtl));
+
+            for (position, index) in starts.iter().chain([&s.len()]).enumerate() {
+                assert_eq!(layout.caret(s, *index).x, FONT_SIZE * position as f32);
+            }
+            assert_eq!(layout.hit_test(s, [FONT_SIZE * 1.4, y]), starts[1]);
+            assert_eq!(layout.hit_test(s, [FONT_SIZE * 1.6, y]), starts[2]);
+            assert_eq!(layout.hit_test(s, [FONT_SIZE * 2.9, y]), s.len());
+        }
+    }
+
+    /// Without shaping glyphs advance by the font's horizontal metrics with
+    /// the pair kerning of the `kern` table.
+    #[cfg(not(feature = "shaping"))]
+    #[test]
+    fn unshaped_glyphs_advance_with_side_bearings_and_kerning() {
+        let glyph = |c, left| TestGlyph {
+            c,
+            contours: vec![test_font::rectangle([left, 0], [900, 700], false)],
+            advance: 1000,
+        };
+        let font_file = test_font::build(
+            800,
+            -200,
+            &[glyph('A', 100), glyph('V', 50)],
+            &[],
+            &[('A', 'V', -200)],
+        );
+        let face = Face::parse(&font_file, 0).unwrap();
+        let s = "AVA";
+        let layout = layout_text(&[&face], s, FONT_SIZE, &Default::default());
+        let scale = FONT_SIZE / 1000.0;
+
+        // "AV" is kerned, "VA" isn't
+        let pens = [0.0, 800.0 * scale, 1800.0 * scale];
+        for ((glyph, pen), left_side_bearing) in
+            layout.glyphs.iter().zip(pens).zip([100.0, 50.0, 100.0])
+        {
+            assert_eq!(glyph.origin[0], pen + left_side_bearing * scale);
+            assert_eq!(glyph.size[0], (900.0 - left_side_bearing) * scale);
+        }
+        let clusters = &layout.lines[0].clusters;
+        assert_eq!(clusters[0].right, pens[1]);
+        assert_eq!(clusters[1].left, pens[1]);
+        assert_eq!(layout.lines[0].width, 2800.0 * scale);
+    }
+
+    #[test]
+    fn missing_chars_fall_back_to_the_replacement_glyph() {
+        let glyph = |c, advance| TestGlyph {
+            c,
+            contours: vec![test_font::rectangle([100, 0], [500, 700], false)],
+            advance,
+        };
+        let first_font = test_font::build(800, -200, &[glyph('a', 1000)], &[], &[]);
+        let second_font = test_font::build(800, -200, &[glyph('?', 600)], &[], &[]);
+        let faces = [
+            Face::parse(&first_font, 0).unwrap(),
+            Face::parse(&second_font, 0).unwrap(),
+        ];
+        let faces: Vec<&Face> = faces.iter().collect();
+        let s = "aXbXa";
+        let styles = Styles {
+            font_size: FONT_SIZE,
+            runs: &[],
+        };
+        let glyph_ids = |replacement_char| {
+            let options = LayoutOptions {
+                replacement_char,
+                ..Default::default()
+            };
+            shape(&faces, s, 0..s.len(), &styles, &options)
+                .iter()
+                .map(|glyph| (glyph.font_index, glyph.glyph_id, glyph.x_advance))
+                .collect::<Vec<_>>()
+        };
+
+        // the replacement comes from the face that has it, with its advance
+        let replacement = (1, faces[1].glyph_index('?').unwrap().0 as u32, 600);
+        let a = (0, faces[0].glyph_index('a').unwrap().0 as u32, 1000);
+        assert_eq!(
+            glyph_ids(Some('?')),
+            [a, replacement, replacement, replacement, a]
+        );
+        // without one, or when no face has it, `.notdef` stays
+        let notdef = (0, 0, 1000);
+        for replacement_char in [None, Some('~')] {
+            assert_eq!(glyph_ids(replacement_char), [a, notdef, notdef, notdef, a]);
+        }
+
+        let layout = layout_text(&faces, s, FONT_SIZE, &Default::default());
+        assert_eq!(layout.missing_chars, ['X', 'b']);
+    }
+}
@@ -1,4 +1,4 @@
-use std::rc::Rc;
+use std::{path::Path, rc::Rc};
 
 use crate::settings::GameSettings;
 
@@ -6,18 +6,34 @@ use super::resources::ResourceManager;
 
 pub struct GpuContext {
     pub device: wgpu::Device,
-    pub surface: wgpu::Surface,
-    pub adapter: wgpu::Adapter,
+    pub surface_format: wgpu::TextureFormat,
 }
 
 pub struct Graphics {
     pub context: GpuContext,
+    target: RenderTarget,
     queue: wgpu::Queue,
     staging_belt: wgpu::util::StagingBelt,
     pub update_queue: Vec<UpdateInfo>,
     pub draw_queue: Vec<DrawCall>,
 }
 
+enum RenderTarget {
+    Surface(SurfaceTarget),
+    Offscreen(OffscreenTarget),
+}
+
+struct SurfaceTarget {
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+}
+
+struct OffscreenTarget {
+    texture: wgpu::Texture,
+    width: u32,
+    height: u32,
+}
+
 pub trait Drawable {
     // fn draw(&self, render_pass: Rc<RefCell<wgpu::RenderPass>>, graphics: &Graphics);
     fn update_queue(
@@ -27,11 +43,14 @@ pub trait Drawable {
         gpu_context: &GpuContext,
     );
     fn get_update_info(&self) -> UpdateInfo;
-    fn get_draw_info(&self, resource_manager: &ResourceManager) -> DrawCall;
+    /// Draws in order, all of them with the same buffers
+    fn get_draw_info(&self, resource_manager: &ResourceManager) -> Vec<DrawCall>;
 }
 
 pub struct UpdateInfo {
     pub target_buffer: Rc<wgpu::Buffer>,
+    /// Byte offset of the write in the target buffer
+    pub offset: wgpu::BufferAddress,
     pub size: wgpu::BufferSize,
     pub content: Rc<Vec<u8>>,
 }
@@ -47,6 +66,7 @@ pub struct DrawIndexedInfo {
     pub index_buffer: Rc<wgpu::Buffer>,
     pub bindgroup: Vec<Rc<wgpu::BindGroup>>,
     pub indices: u32,
+    pub first_instance: u32,
     pub instance: u32,
 }
 
@@ -68,6 +88,7 @@ fn get_backend() -> wgpu::InstanceDescriptor {
 }
 
 impl Graphics {
+    /// Draws into the window's surface, sized to the window's inner size.
     pub fn new(window: &winit::window::Window, settings: &GameSettings) -> Self {
         // surface queue config
         let instance = wgpu::Instance::new(get_backend());
@@ -79,38 +100,99 @@ impl Graphics {
                 compatible_surface: Some(&surface),
             }))
             .unwrap();
-        let (device, queue) = pollster::block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: Some("Primary Device"),
-                features: wgpu::Features::default(),
-                limits: wgpu::Limits::default(),
-            },
-            None,
-        ))
-        .unwrap();
-        println!("{:?}", adapter.get_info());
+        let (device, queue) = request_device(&adapter);
 
+        let surface_format = choose_surface_format(
+            &surface.get_capabilities(&adapter).formats,
+            settings.get_srgb_surface(),
+        );
+        let window_size = window.inner_size();
         let surface_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_capabilities(&adapter).formats[0],
-            width: settings.get_window_width(),
-            height: settings.get_window_height(),
+            format: surface_format,
+            width: window_size.width.max(1),
+            height: window_size.height.max(1),
             present_mode: wgpu::PresentMode::AutoVsync,
             alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            view_formats: vec![surface.get_capabilities(&adapter).formats[0]],
+            view_formats: vec![surface_format],
         };
         surface.configure(&device, &surface_config);
+
+        Self::from_parts(
+            device,
+            queue,
+            adapter,
+            surface_format,
+            RenderTarget::Surface(SurfaceTarget {
+                surface,
+                config: surface_config,
+            }),
+        )
+    }
+
+    /// Renders into an offscreen texture instead of a window, falling back to
+    /// a software adapter when there is no GPU.
+    pub fn new_headless(settings: &GameSettings) -> Self {
+        let mut backend = get_backend();
+        // software rasterizers like llvmpipe are often only exposed through GL
+        backend.backends |= wgpu::Backends::GL;
+        let instance = wgpu::Instance::new(backend);
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptionsBase {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            }))
+            .or_else(|| {
+                pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptionsBase {
+                    power_preference: wgpu::PowerPreference::LowPower,
+                    force_fallback_adapter: true,
+                    compatible_surface: None,
+                }))
+            })
+            .expect("No adapter available for headless rendering!");
+        let (device, queue) = request_device(&adapter);
+
+        let surface_format = if settings.get_srgb_surface() {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+        let target = OffscreenTarget::new(
+            &device,
+            surface_format,
+            settings.get_window_width(),
+            settings.get_window_height(),
+        );
+
+        Self::from_parts(
+            device,
+            queue,
+            adapter,
+            surface_format,
+            RenderTarget::Offscreen(target),
+        )
+    }
+
+    fn from_parts(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        adapter: wgpu::Adapter,
+        surface_format: wgpu::TextureFormat,
+        target: RenderTarget,
+    ) -> Self {
+        log::info!("{:?}", adapter.get_info());
         let staging_belt = wgpu::util::StagingBelt::new(16 * 256); // max 256 utf-16
 
         // Font config
         let context = GpuContext {
             device,
-            surface,
-            adapter,
+            surface_format,
         };
 
         Graphics {
             context,
+            target,
             queue,
             staging_belt,
             update_queue: Vec::new(),
@@ -118,16 +200,68 @@ impl Graphics {
         }
     }
 
+    /// Size of the render target in physical pixels.
+    pub fn get_size(&self) -> [u32; 2] {
+        match &self.target {
+            RenderTarget::Surface(target) => [target.config.width, target.config.height],
+            RenderTarget::Offscreen(target) => [target.width, target.height],
+        }
+    }
+
+    /// Resizes the render target, `width` and `height` are in physical
+    /// pixels. Zero sizes, like minimized windows, are ignored.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 || self.get_size() == [width, height] {
+            return;
+        }
+        match &mut self.target {
+            RenderTarget::Surface(target) => {
+                target.config.width = width;
+                target.config.height = height;
+                target
+                    .surface
+                    .configure(&self.context.device, &target.config);
+            }
+            RenderTarget::Offscreen(target) => {
+                target.texture.destroy();
+                *target = OffscreenTarget::new(
+                    &self.context.device,
+                    self.context.surface_format,
+                    width,
+                    height,
+                );
+            }
+        }
+    }
+
     pub fn draw(&mut self) {
         // get view
-        let texture = self
-            .context
-            .surface
-            .get_current_texture()
-            .expect("Error getting surface texture!");
-        let view = texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let (frame, view) = match &self.target {
+            RenderTarget::Surface(target) => {
+                let frame = match target.surface.get_current_texture() {
+                    Ok(frame) => frame,
+                    // the window changed under us, skip this frame
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        target
+                            .surface
+                            .configure(&self.context.device, &target.config);
+                        self.draw_queue.clear();
+                        return;
+                    }
+                    Err(error) => panic!("Error getting surface texture: {error}"),
+                };
+                let view = frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(frame), view)
+            }
+            RenderTarget::Offscreen(target) => (
+                None,
+                target
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+        };
         // preset
         let color_attach = [Some(wgpu::RenderPassColorAttachment {
             view: &view,
@@ -160,7 +294,7 @@ impl Graphics {
                     .write_buffer(
                         &mut command_encoder,
                         update.target_buffer.as_ref(),
-                        0,
+                        update.offset,
                         update.size,
                         &self.context.device,
                     )
@@ -186,7 +320,11 @@ impl Graphics {
                             info.index_buffer.slice(..),
                             wgpu::IndexFormat::Uint16,
                         );
-                        render_pass.draw_indexed(0..info.indices, 0, 0..info.instance);
+                        render_pass.draw_indexed(
+                            0..info.indices,
+                            0,
+                            info.first_instance..info.first_instance + info.instance,
+                        );
                     }
                     DrawCall::Draw(_) => todo!(),
                 }
@@ -200,6 +338,135 @@ impl Graphics {
             self.staging_belt.recall();
         }
 
-        texture.present();
+        if let Some(frame) = frame {
+            frame.present();
+        }
+    }
+
+    /// Copies the last drawn frame back to the CPU as tightly packed RGBA8
+    /// rows. Only available for headless graphics.
+    pub fn read_frame(&self) -> Option<Vec<u8>> {
+        let RenderTarget::Offscreen(target) = &self.target else {
+            return None;
+        };
+        let unpadded_bytes_per_row = target.width * 4;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(alignment) * alignment;
+        let readback_buffer = self.context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * target.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut command_encoder =
+            self.context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Readback Encoder"),
+                });
+        command_encoder.copy_texture_to_buffer(
+            target.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: target.width,
+                height: target.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(command_encoder.finish()));
+
+        let buffer_slice = readback_buffer.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("Unable to map readback buffer!")
+        });
+        self.context.device.poll(wgpu::Maintain::Wait);
+        let pixels = buffer_slice
+            .get_mapped_range()
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| row[..unpadded_bytes_per_row as usize].iter().copied())
+            .collect();
+        readback_buffer.unmap();
+
+        Some(pixels)
     }
+
+    /// Writes the last drawn frame to a PNG file. Only available for headless
+    /// graphics.
+    pub fn save_frame<P: AsRef<Path>>(&self, path: P) {
+        let RenderTarget::Offscreen(target) = &self.target else {
+            panic!("Only headless graphics can be saved to an image!");
+        };
+        let pixels = self.read_frame().unwrap();
+        let file = std::fs::File::create(path).expect("Unable to create image file!");
+        let mut encoder =
+            png::Encoder::new(std::io::BufWriter::new(file), target.width, target.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&pixels))
+            .expect("Unable to write image!");
+    }
+}
+
+impl OffscreenTarget {
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        OffscreenTarget {
+            texture,
+            width,
+            height,
+        }
+    }
+}
+
+/// Picks the first sRGB or linear format the surface supports, the first
+/// format when there is none of the kind.
+fn choose_surface_format(formats: &[wgpu::TextureFormat], srgb: bool) -> wgpu::TextureFormat {
+    formats
+        .iter()
+        .copied()
+        .find(|format| format.describe().srgb == srgb)
+        .unwrap_or_else(|| {
+            log::warn!(
+                "No {} surface format, using {:?}",
+                if srgb { "sRGB" } else { "linear" },
+                formats[0]
+            );
+            formats[0]
+        })
+}
+
+fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("Primary Device"),
+            features: wgpu::Features::default(),
+            limits: wgpu::Limits::default(),
+        },
+        None,
+    ))
+    .unwrap()
 }
@@ -1,37 +1,61 @@
 use std::rc::Rc;
 
-use self::text::Text;
+use self::text::{Text, TextAnchor, TextSpan, TextStyle};
 
 use super::{
-    font_manager::FontManager,
+    font_manager::{
+        text_layout::{CaretRect, TextMetrics},
+        FontManager,
+    },
     graphics::{DrawCall, Drawable, GpuContext, UpdateInfo},
     resources::ResourceManager,
 };
 
-mod text;
+pub mod text;
+
+/// Size of the window in physical pixels and its DPI scale factor, to convert
+/// logical pixels into normalized device coordinates.
+#[derive(Debug, Clone, Copy)]
+pub struct Viewport {
+    pub size: [f32; 2],
+    pub scale_factor: f32,
+}
 
 pub struct GuiManager {
-    window_size: [f32; 2],
-    content_list: Vec<Box<dyn Drawable>>,
+    viewport: Viewport,
+    text_list: Vec<Text>,
 }
 
 impl GuiManager {
-    pub fn new(window_width: u32, window_height: u32) -> Self {
+    pub fn new(window_width: u32, window_height: u32, scale_factor: f64) -> Self {
         GuiManager {
-            window_size: [window_width as f32, window_height as f32],
-            content_list: Vec::new(),
+            viewport: Viewport {
+                size: [window_width as f32, window_height as f32],
+                scale_factor: scale_factor as f32,
+            },
+            text_list: Vec::new(),
         }
     }
 
+    /// Adds a text at `position` in logical pixels and returns its index.
     pub fn add_text(
         &mut self,
         content: String,
-        font_size: f32,
+        position: [f32; 2],
+        style: TextStyle,
         font_manager: Rc<FontManager>,
         gpu_context: &GpuContext,
-    ) {
-        let text = Text::from_string(content, font_size, font_manager, gpu_context);
-        self.content_list.push(Box::new(text));
+    ) -> usize {
+        let text = Text::from_string(
+            content,
+            position,
+            style,
+            self.viewport,
+            font_manager,
+            gpu_context,
+        );
+        self.text_list.push(text);
+        self.text_list.len() - 1
     }
 
     pub fn update_at(
@@ -41,13 +65,79 @@ impl GuiManager {
         update_queue: &mut Vec<UpdateInfo>,
         context: &GpuContext,
     ) {
-        // self.content_list[index]
-        self.content_list[index].update_queue(content, update_queue, context);
+        self.text_list[index].update_queue(content, update_queue, context);
+    }
+
+    pub fn move_text(
+        &mut self,
+        index: usize,
+        position: [f32; 2],
+        anchor: TextAnchor,
+        update_queue: &mut Vec<UpdateInfo>,
+        context: &GpuContext,
+    ) {
+        self.text_list[index].set_position(position, anchor, update_queue, context);
+    }
+
+    pub fn set_text_spans(
+        &mut self,
+        index: usize,
+        spans: Vec<TextSpan>,
+        update_queue: &mut Vec<UpdateInfo>,
+        context: &GpuContext,
+    ) {
+        self.text_list[index].set_spans(spans, update_queue, context);
+    }
+
+    /// Updates the window size in physical pixels and the scale factor, then
+    /// lays every text out again.
+    pub fn resize(
+        &mut self,
+        window_width: u32,
+        window_height: u32,
+        scale_factor: f64,
+        update_queue: &mut Vec<UpdateInfo>,
+        context: &GpuContext,
+    ) {
+        self.viewport = Viewport {
+            size: [window_width as f32, window_height as f32],
+            scale_factor: scale_factor as f32,
+        };
+        for text in self.text_list.iter_mut() {
+            text.set_viewport(self.viewport, update_queue, context);
+        }
+    }
+
+    /// Measures `s` as a text with `style` would be laid out in the current
+    /// viewport, in logical pixels.
+    pub fn measure_text(
+        &self,
+        s: &str,
+        style: &TextStyle,
+        font_manager: &FontManager,
+    ) -> TextMetrics {
+        Text::measure(s, style, &[], self.viewport, font_manager)
+    }
+
+    pub fn text_metrics(&self, index: usize) -> TextMetrics {
+        self.text_list[index].metrics()
+    }
+
+    pub fn caret_rect(&self, index: usize, char_index: usize) -> CaretRect {
+        self.text_list[index].caret_rect(char_index)
+    }
+
+    pub fn hit_test(&self, index: usize, point: [f32; 2]) -> usize {
+        self.text_list[index].hit_test(point)
+    }
+
+    pub fn missing_chars(&self, index: usize) -> &[char] {
+        self.text_list[index].missing_chars()
     }
 
     pub fn draw_queue(&self, resource_manager: &ResourceManager, draw_queue: &mut Vec<DrawCall>) {
-        self.content_list.iter().for_each(|thing| {
-            draw_queue.push(thing.get_draw_info(resource_manager));
+        self.text_list.iter().for_each(|thing| {
+            draw_queue.extend(thing.get_draw_info(resource_manager));
         });
     }
 }
@@ -1,33 +1,157 @@
-use std::{rc::Rc, vec};
+use std::{collections::HashMap, ops::Range, rc::Rc, vec};
 
 use wgpu::util::DeviceExt;
 
+use super::Viewport;
 use crate::core::{
-    font_manager::{string_data::CharData, FontManager},
+    font_manager::{
+        font_graphics::{Antialiasing, FillRule, GlyphPass},
+        string_data::CharData,
+        text_layout::{
+            layout_rich_text, CaretRect, LayoutOptions, PositionedGlyph, StyleRun, TextAlignment,
+            TextLayout, TextMetrics,
+        },
+        FontManager,
+    },
     graphics::{DrawCall, DrawIndexedInfo, Drawable, GpuContext, UpdateInfo},
     resources::ResourceManager,
 };
 
+/// Which point of the text's layout box sits at its position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextAnchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl TextAnchor {
+    /// Horizontal and vertical fraction of the layout box before the anchor
+    fn factors(&self) -> [f32; 2] {
+        match self {
+            TextAnchor::TopLeft => [0.0, 0.0],
+            TextAnchor::Top => [0.5, 0.0],
+            TextAnchor::TopRight => [1.0, 0.0],
+            TextAnchor::Left => [0.0, 0.5],
+            TextAnchor::Center => [0.5, 0.5],
+            TextAnchor::Right => [1.0, 0.5],
+            TextAnchor::BottomLeft => [0.0, 1.0],
+            TextAnchor::Bottom => [0.5, 1.0],
+            TextAnchor::BottomRight => [1.0, 1.0],
+        }
+    }
+}
+
+/// How a text is sized, wrapped and placed around its position. Sizes are in
+/// logical pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct TextStyle {
+    pub font_size: f32,
+    pub anchor: TextAnchor,
+    /// Wraps lines wider than this
+    pub max_width: Option<f32>,
+    pub alignment: TextAlignment,
+    /// Multiplier of the font's line height
+    pub line_spacing: f32,
+    /// Drawn in place of characters missing from the font instead of
+    /// `.notdef`
+    pub replacement_char: Option<char>,
+    /// RGBA with straight alpha, from 0 to 1
+    pub color: [f32; 4],
+    pub outline: Option<TextOutline>,
+    pub shadow: Option<TextShadow>,
+    /// Fonts are made for the nonzero rule, even-odd leaves holes where
+    /// contours overlap
+    pub fill_rule: FillRule,
+    /// Subpixel antialiasing sharpens small text on LCD displays
+    pub antialiasing: Antialiasing,
+}
+
+/// Stroke around the glyphs, drawn under the fill of every glyph of the text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextOutline {
+    /// In logical pixels
+    pub width: f32,
+    /// RGBA with straight alpha, from 0 to 1
+    pub color: [f32; 4],
+}
+
+/// Shadow of the glyphs and their outline, drawn under both.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextShadow {
+    /// In logical pixels, y pointing down
+    pub offset: [f32; 2],
+    /// Zero for a sharp shadow, in logical pixels
+    pub blur_radius: f32,
+    /// RGBA with straight alpha, from 0 to 1
+    pub color: [f32; 4],
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            font_size: 32.0,
+            anchor: TextAnchor::TopLeft,
+            max_width: None,
+            alignment: TextAlignment::Left,
+            line_spacing: 1.0,
+            replacement_char: None,
+            color: [1.0, 1.0, 1.0, 1.0],
+            outline: None,
+            shadow: None,
+            fill_rule: FillRule::NonZero,
+            antialiasing: Antialiasing::Grayscale,
+        }
+    }
+}
+
+/// Overrides the style of a byte range of a text's content, later spans win
+/// where they overlap.
+#[derive(Debug, Clone, Default)]
+pub struct TextSpan {
+    pub range: Range<usize>,
+    pub color: Option<[f32; 4]>,
+    /// In logical pixels
+    pub font_size: Option<f32>,
+    /// Index into the font list of the font tried first, e.g. a bold or
+    /// italic instance, characters it lacks fall back through the list
+    pub font: Option<usize>,
+}
+
 pub struct Text {
-    font_size: f32,
+    content: String,
+    position: [f32; 2],
+    style: TextStyle,
+    spans: Vec<TextSpan>,
+    viewport: Viewport,
     font_manager: Rc<FontManager>,
+    layout: TextLayout,
+    /// Fills drawn together by the subpixel passes, see [`fill_runs`]
+    fill_runs: Vec<Range<u32>>,
     string_vec_buffer: Rc<wgpu::Buffer>,
     raw_content: Rc<Vec<u8>>,
 }
 
 impl Text {
+    /// `position` is in logical pixels, from the top left corner of the
+    /// window.
     pub fn from_string(
         s: String,
-        font_size: f32,
+        position: [f32; 2],
+        style: TextStyle,
+        viewport: Viewport,
         font_manager: Rc<FontManager>,
         gpu_context: &GpuContext,
     ) -> Self {
-        let string_vec = Self::get_string_vec(
-            s,
-            font_size,
-            font_manager.get_face(),
-            font_manager.get_window_size(),
-        );
+        let (string_vec, layout) =
+            Self::get_string_vec(&s, position, &style, &[], viewport, &font_manager);
         let raw_content = Rc::new(bytemuck::cast_slice(&string_vec).to_vec());
         let string_vec_buffer =
             gpu_context
@@ -41,62 +165,268 @@ impl Text {
                 });
 
         Text {
-            font_size,
+            content: s,
+            position,
+            fill_runs: Self::fill_runs(&style, &layout),
+            style,
+            spans: Vec::new(),
+            viewport,
             font_manager,
+            layout,
             string_vec_buffer: Rc::new(string_vec_buffer),
             raw_content,
         }
     }
 
     pub fn update_string(&mut self, s: String) {
-        let string_vec = Self::get_string_vec(
-            s,
-            self.font_size,
-            self.font_manager.get_face(),
-            self.font_manager.get_window_size(),
-        );
-        self.raw_content = Rc::new(bytemuck::cast_slice(&string_vec).to_vec());
+        self.content = s;
+        self.layout();
     }
 
-    fn get_string_vec(
-        s: String,
-        font_size: f32,
-        face: &owned_ttf_parser::Face,
-        window_size: [f32; 2],
-    ) -> Vec<CharData> {
-        let mut last_width = 0.0;
-        let mut x_drift = -0.8;
-        let mut string_vec = Vec::new();
-        for this_char in s.chars() {
-            let glyph_index = face.glyph_index(this_char).unwrap();
-            let info = face.glyph_bounding_box(glyph_index).unwrap_or_else(|| {
-                face.glyph_bounding_box(owned_ttf_parser::GlyphId(299))
-                    .unwrap()
-            });
-            x_drift += last_width / window_size[0] * 2.0 * 1.05;
-            let y_drift =
-                info.y_min as f32 / face.units_per_em() as f32 * font_size / window_size[1] * 2.0;
-            string_vec.push(CharData::new(
-                glyph_index.0 as u32,
-                font_size,
-                [x_drift, -0.3 + y_drift],
-            ));
-            last_width = info.width() as f32 / face.units_per_em() as f32 * font_size;
+    /// Characters of the content the font could not render, in order of
+    /// first appearance.
+    #[inline]
+    pub fn missing_chars(&self) -> &[char] {
+        &self.layout.missing_chars
+    }
+
+    /// Size of the laid out content and boxes of its glyphs, in logical
+    /// pixels.
+    pub fn metrics(&self) -> TextMetrics {
+        self.layout
+            .metrics()
+            .scaled(1.0 / self.viewport.scale_factor)
+    }
+
+    /// Caret before the character at byte `index` of the content, in logical
+    /// pixels from the top left corner of the window. `index` should be a
+    /// grapheme boundary, the end of the content is one.
+    pub fn caret_rect(&self, index: usize) -> CaretRect {
+        let scale_factor = self.viewport.scale_factor;
+        let [left, top] = self.layout_box_origin();
+        let caret = self.layout.caret(&self.content, index);
+        CaretRect {
+            x: (left + caret.x) / scale_factor,
+            top: (top + caret.top) / scale_factor,
+            bottom: (top + caret.bottom) / scale_factor,
+            ..caret
         }
+    }
 
-        string_vec
+    /// Byte index of the content at the grapheme boundary nearest to `point`,
+    /// in logical pixels from the top left corner of the window, e.g. where
+    /// a click puts the caret.
+    pub fn hit_test(&self, point: [f32; 2]) -> usize {
+        let scale_factor = self.viewport.scale_factor;
+        let [left, top] = self.layout_box_origin();
+        self.layout.hit_test(
+            &self.content,
+            [
+                point[0] * scale_factor - left,
+                point[1] * scale_factor - top,
+            ],
+        )
     }
-}
 
-impl Drawable for Text {
-    fn update_queue(
+    /// Measures `s` laid out with `style` and `spans` as a text would be, in
+    /// logical pixels, without creating one.
+    pub fn measure(
+        s: &str,
+        style: &TextStyle,
+        spans: &[TextSpan],
+        viewport: Viewport,
+        font_manager: &FontManager,
+    ) -> TextMetrics {
+        Self::layout_content(s, style, spans, viewport, font_manager)
+            .metrics()
+            .scaled(1.0 / viewport.scale_factor)
+    }
+
+    /// Moves the text to `position` in logical pixels, anchored at `anchor`.
+    pub fn set_position(
         &mut self,
-        content: Vec<u8>,
+        position: [f32; 2],
+        anchor: TextAnchor,
         update_queue: &mut Vec<UpdateInfo>,
         gpu_context: &GpuContext,
     ) {
-        let s = String::from_utf8(content).unwrap();
-        self.update_string(s);
+        self.position = position;
+        self.style.anchor = anchor;
+        self.layout();
+        self.queue_upload(update_queue, gpu_context);
+    }
+
+    /// Replaces the style overrides of parts of the content.
+    pub fn set_spans(
+        &mut self,
+        spans: Vec<TextSpan>,
+        update_queue: &mut Vec<UpdateInfo>,
+        gpu_context: &GpuContext,
+    ) {
+        self.spans = spans;
+        self.layout();
+        self.queue_upload(update_queue, gpu_context);
+    }
+
+    /// Lays the text out again for a resized window or a new scale factor.
+    pub fn set_viewport(
+        &mut self,
+        viewport: Viewport,
+        update_queue: &mut Vec<UpdateInfo>,
+        gpu_context: &GpuContext,
+    ) {
+        self.viewport = viewport;
+        self.layout();
+        self.queue_upload(update_queue, gpu_context);
+    }
+
+    fn layout(&mut self) {
+        let (string_vec, layout) = Self::get_string_vec(
+            &self.content,
+            self.position,
+            &self.style,
+            &self.spans,
+            self.viewport,
+            &self.font_manager,
+        );
+        self.fill_runs = Self::fill_runs(&self.style, &layout);
+        self.layout = layout;
+        self.raw_content = Rc::new(bytemuck::cast_slice(&string_vec).to_vec());
+    }
+
+    fn fill_runs(style: &TextStyle, layout: &TextLayout) -> Vec<Range<u32>> {
+        match style.antialiasing {
+            Antialiasing::Grayscale => Vec::new(),
+            Antialiasing::Subpixel => fill_runs(&layout.glyphs),
+        }
+    }
+
+    /// Lays `s` out in physical pixels.
+    fn layout_content(
+        s: &str,
+        style: &TextStyle,
+        spans: &[TextSpan],
+        viewport: Viewport,
+        font_manager: &FontManager,
+    ) -> TextLayout {
+        let scale_factor = viewport.scale_factor;
+        let options = LayoutOptions {
+            max_width: style.max_width.map(|width| width * scale_factor),
+            alignment: style.alignment,
+            line_spacing: style.line_spacing,
+            replacement_char: style.replacement_char,
+        };
+        let runs = spans
+            .iter()
+            .map(|span| StyleRun {
+                range: span.range.clone(),
+                font_size: span.font_size.map(|size| size * scale_factor),
+                font_index: span.font,
+            })
+            .collect::<Vec<_>>();
+        layout_rich_text(
+            &font_manager.get_faces(),
+            s,
+            style.font_size * scale_factor,
+            &runs,
+            &options,
+        )
+    }
+
+    /// Top left corner of the layout box, in physical pixels.
+    fn layout_box_origin(&self) -> [f32; 2] {
+        Self::box_origin(
+            self.position,
+            &self.style,
+            &self.layout,
+            self.viewport.scale_factor,
+        )
+    }
+
+    fn box_origin(
+        position: [f32; 2],
+        style: &TextStyle,
+        layout: &TextLayout,
+        scale_factor: f32,
+    ) -> [f32; 2] {
+        let [anchor_x, anchor_y] = style.anchor.factors();
+        [
+            position[0] * scale_factor - layout.width * anchor_x,
+            position[1] * scale_factor - layout.height * anchor_y,
+        ]
+    }
+
+    fn get_string_vec(
+        s: &str,
+        position: [f32; 2],
+        style: &TextStyle,
+        spans: &[TextSpan],
+        viewport: Viewport,
+        font_manager: &FontManager,
+    ) -> (Vec<CharData>, TextLayout) {
+        let scale_factor = viewport.scale_factor;
+        let window_size = viewport.size;
+        let layout = Self::layout_content(s, style, spans, viewport, font_manager);
+
+        let [left, top] = Self::box_origin(position, style, &layout, scale_factor);
+        let baseline = top + layout.ascent;
+        font_manager.report_missing_chars(&layout.missing_chars);
+        font_manager.request_glyphs(&layout.glyphs);
+        // every glyph is drawn once per pass, shadows first and fills last so
+        // no shadow or outline covers the fill of a neighbouring glyph. Passes
+        // without color use the color of the glyph's span.
+        let outline_width = style.outline.map_or(0.0, |outline| outline.width);
+        let mut passes = Vec::new();
+        if let Some(shadow) = style.shadow {
+            passes.push((
+                Some(shadow.color),
+                [
+                    shadow.offset[0],
+                    shadow.offset[1],
+                    outline_width,
+                    shadow.blur_radius,
+                ],
+            ));
+        }
+        if let Some(outline) = style.outline {
+            passes.push((Some(outline.color), [0.0, 0.0, outline.width, 0.0]));
+        }
+        passes.push((None, [0.0; 4]));
+
+        let string_vec = passes
+            .into_iter()
+            .flat_map(|(pass_color, effect)| {
+                layout.glyphs.iter().map(move |glyph| {
+                    let color = pass_color.unwrap_or_else(|| {
+                        spans
+                            .iter()
+                            .rev()
+                            .filter(|span| span.range.contains(&glyph.cluster))
+                            .find_map(|span| span.color)
+                            .unwrap_or(style.color)
+                    });
+                    CharData::new(
+                        glyph.font_index,
+                        glyph.glyph_id,
+                        glyph.pixels_per_em,
+                        [
+                            (left + glyph.origin[0]) / window_size[0] * 2.0 - 1.0,
+                            1.0 - (baseline + glyph.origin[1]) / window_size[1] * 2.0,
+                        ],
+                        color,
+                        effect.map(|length| length * scale_factor),
+                    )
+                })
+            })
+            .collect();
+
+        (string_vec, layout)
+    }
+
+    fn queue_upload(&mut self, update_queue: &mut Vec<UpdateInfo>, gpu_context: &GpuContext) {
+        if self.raw_content.is_empty() {
+            return;
+        }
         if self.raw_content.len() as u64 > self.string_vec_buffer.size() {
             self.string_vec_buffer.destroy();
             self.string_vec_buffer =
@@ -111,29 +441,174 @@ impl Drawable for Text {
         }
         update_queue.push(self.get_update_info());
     }
+}
+
+impl Drawable for Text {
+    fn update_queue(
+        &mut self,
+        content: Vec<u8>,
+        update_queue: &mut Vec<UpdateInfo>,
+        gpu_context: &GpuContext,
+    ) {
+        let s = String::from_utf8(content).unwrap();
+        self.update_string(s);
+        self.queue_upload(update_queue, gpu_context);
+    }
 
     fn get_update_info(&self) -> UpdateInfo {
         UpdateInfo {
             target_buffer: self.string_vec_buffer.clone(),
+            offset: 0,
             size: wgpu::BufferSize::new(self.raw_content.len() as _).unwrap(),
             content: self.raw_content.clone(),
         }
     }
 
-    fn get_draw_info(&self, resource_manager: &ResourceManager) -> DrawCall {
-        DrawCall::DrawIndexed(DrawIndexedInfo {
-            pipeline: resource_manager.font.render_pipeline.clone(),
-            vertex_buffer: vec![
-                resource_manager.font.vertex_buffer.clone(),
-                self.string_vec_buffer.clone(),
-            ],
-            index_buffer: resource_manager.font.index_buffer.clone(),
-            bindgroup: vec![
-                resource_manager.font.uniform_bindgroup.clone(),
-                resource_manager.font.font_data_bindgroup.clone(),
-            ],
-            indices: 6,
-            instance: (self.raw_content.len() / std::mem::size_of::<CharData>()) as u32,
-        })
+    fn get_draw_info(&self, resource_manager: &ResourceManager) -> Vec<DrawCall> {
+        let instances = (self.raw_content.len() / std::mem::size_of::<CharData>()) as u32;
+        // the fills are the last pass of glyphs, see `get_string_vec`
+        let fills = self.layout.glyphs.len() as u32;
+        let passes = match self.style.antialiasing {
+            Antialiasing::Grayscale => vec![(GlyphPass::Grayscale, 0..instances)],
+            Antialiasing::Subpixel => {
+                let first_fill = instances - fills;
+                let mut passes = vec![(GlyphPass::Grayscale, 0..first_fill)];
+                // each run is masked and colored before the next one
+                for run in self.fill_runs.iter() {
+                    let run = first_fill + run.start..first_fill + run.end;
+                    passes.push((GlyphPass::SubpixelMask, run.clone()));
+                    passes.push((GlyphPass::SubpixelColor, run));
+                }
+                passes
+            }
+        };
+        passes
+            .into_iter()
+            .filter(|(_, range)| !range.is_empty())
+            .map(|(pass, range)| {
+                DrawCall::DrawIndexed(DrawIndexedInfo {
+                    pipeline: resource_manager.font.pipeline(self.style.fill_rule, pass),
+                    vertex_buffer: vec![
+                        resource_manager.font.vertex_buffer.clone(),
+                        self.string_vec_buffer.clone(),
+                    ],
+                    index_buffer: resource_manager.font.index_buffer.clone(),
+                    bindgroup: vec![
+                        resource_manager.font.uniform_bindgroup.clone(),
+                        resource_manager.font.glyph_buffers.bind_group(),
+                    ],
+                    indices: 6,
+                    first_instance: range.start,
+                    instance: range.len() as u32,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Splits `glyphs` into runs whose quads don't overlap. A pixel masked by two
+/// glyphs of the same subpixel pass would get the color of both added on top
+/// of the dimmed background, runs of glyphs that don't share pixels are drawn
+/// one after another instead. Each run costs a mask and a color draw call,
+/// text whose glyphs only touch, like most text at the usual spacing, is a
+/// single run, kerned pairs and stacked marks start a new one.
+fn fill_runs(glyphs: &[PositionedGlyph]) -> Vec<Range<u32>> {
+    // pixels whose centers are in the quads of vs_main, the bounding box
+    // grown by half a pixel, from the first to past the last column and row
+    let pixels = |glyph: &PositionedGlyph| {
+        let top = glyph.origin[1] - glyph.size[1];
+        [
+            (glyph.origin[0] - 1.0).floor() as i32 + 1,
+            (top - 1.0).floor() as i32 + 1,
+            (glyph.origin[0] + glyph.size[0]).ceil() as i32,
+            glyph.origin[1].ceil() as i32,
+        ]
+    };
+    // the pixels of the current run by grid cell, so that a glyph is only
+    // compared with the glyphs of the run around it
+    const CELL_SIZE: i32 = 64;
+    let cells = |[left, top, right, bottom]: [i32; 4]| {
+        let columns = left.div_euclid(CELL_SIZE)..=(right - 1).div_euclid(CELL_SIZE);
+        let rows = top.div_euclid(CELL_SIZE)..=(bottom - 1).div_euclid(CELL_SIZE);
+        rows.flat_map(move |row| columns.clone().map(move |column| [column, row]))
+    };
+    let mut run_pixels: HashMap<[i32; 2], Vec<[i32; 4]>> = HashMap::new();
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for (index, glyph) in glyphs.iter().enumerate() {
+        let [left, top, right, bottom] = pixels(glyph);
+        if left >= right || top >= bottom {
+            continue;
+        }
+        let overlaps = cells([left, top, right, bottom]).any(|cell| {
+            run_pixels.get(&cell).is_some_and(|others| {
+                others.iter().any(|other| {
+                    left < other[2] && other[0] < right && top < other[3] && other[1] < bottom
+                })
+            })
+        });
+        if overlaps {
+            runs.push(start as u32..index as u32);
+            start = index;
+            run_pixels.clear();
+        }
+        for cell in cells([left, top, right, bottom]) {
+            run_pixels
+                .entry(cell)
+                .or_default()
+                .push([left, top, right, bottom]);
+        }
+    }
+    if start < glyphs.len() {
+        runs.push(start as u32..glyphs.len() as u32);
+    }
+
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A glyph `size` pixels wide and tall with its bottom left corner at
+    /// `origin`.
+    fn glyph(origin: [f32; 2], size: f32) -> PositionedGlyph {
+        PositionedGlyph {
+            font_index: 0,
+            glyph_id: 1,
+            origin,
+            size: [size, size],
+            pixels_per_em: 20.0,
+            cluster: 0,
+        }
+    }
+
+    #[test]
+    fn touching_glyphs_fill_in_one_run() {
+        // lines of glyphs next to each other, the lines touching too
+        let glyphs: Vec<_> = (0..10_000)
+            .map(|index| {
+                let [column, line] = [index % 100, index / 100];
+                glyph([column as f32 * 10.0, line as f32 * 10.0], 10.0)
+            })
+            .collect();
+        let runs = fill_runs(&glyphs);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0], 0..10_000);
+    }
+
+    #[test]
+    fn overlapping_glyphs_start_a_run() {
+        let glyphs = [
+            glyph([0.0, 10.0], 10.0),
+            // the half pixel borders of touching quads share no pixel
+            glyph([10.0, 10.0], 10.0),
+            glyph([19.5, 10.0], 10.0),
+            // overlaps the first glyph only, which is in the previous run
+            glyph([5.0, 10.0], 4.0),
+            // overlaps the glyph before
+            glyph([6.0, 10.0], 2.0),
+        ];
+        assert_eq!(fill_runs(&glyphs), [0..2, 2..4, 4..5]);
     }
 }
@@ -3,14 +3,26 @@ use std::{path::Path, rc::Rc};
 use crate::settings::GameSettings;
 
 use self::{
-    font_manager::FontManager, game_time::GameTimeManager, graphics::Graphics,
-    gui_manager::GuiManager, resources::ResourceManager, user_input::UserInput,
+    font_manager::{
+        font_collection::FontSource,
+        font_graphics::{CoverageCurve, SubpixelOrder},
+        text_layout::{CaretRect, TextMetrics},
+        FontManager,
+    },
+    game_time::GameTimeManager,
+    graphics::{Graphics, UpdateInfo},
+    gui_manager::{
+        text::{TextAnchor, TextSpan, TextStyle},
+        GuiManager,
+    },
+    resources::ResourceManager,
+    user_input::UserInput,
 };
 
-mod font_manager;
+pub mod font_manager;
 mod game_time;
 mod graphics;
-mod gui_manager;
+pub mod gui_manager;
 mod resources;
 mod tables;
 mod user_input;
@@ -27,19 +39,51 @@ pub struct Controller {
 
 impl Controller {
     pub fn new(window: &winit::window::Window, game_settings: Rc<GameSettings>) -> Self {
-        let font_path = Path::new("data").join("chi1.ttf");
-        let font_manager = Rc::new(FontManager::new(
-            font_path,
-            game_settings.get_window_width(),
-            game_settings.get_window_height(),
-        ));
-        let gui_manager = GuiManager::new(
-            game_settings.get_window_width(),
-            game_settings.get_window_height(),
-        );
-        let input = UserInput::new();
         let graphics = Graphics::new(window, &game_settings);
-        let resource_manager = ResourceManager::new(&font_manager, &graphics.context);
+        Self::with_graphics(graphics, game_settings, window.scale_factor())
+    }
+
+    /// Creates a controller drawing into an offscreen texture, see
+    /// [`Controller::save_frame`].
+    pub fn new_headless(game_settings: Rc<GameSettings>) -> Self {
+        let graphics = Graphics::new_headless(&game_settings);
+        Self::with_graphics(graphics, game_settings, 1.0)
+    }
+
+    fn with_graphics(
+        graphics: Graphics,
+        game_settings: Rc<GameSettings>,
+        scale_factor: f64,
+    ) -> Self {
+        let font_sources: Vec<_> = game_settings
+            .get_font_list()
+            .iter()
+            .map(|font| {
+                let font_source = FontSource::parse(font);
+                FontSource {
+                    path: Path::new("data").join(font_source.path),
+                    ..font_source
+                }
+            })
+            .collect();
+        let font_manager = Rc::new(FontManager::new(&font_sources));
+        let [window_width, window_height] = graphics.get_size();
+        let gui_manager = GuiManager::new(window_width, window_height, scale_factor);
+        let input = UserInput::new();
+        let resource_manager = ResourceManager::new(
+            &font_manager,
+            &graphics.context,
+            [window_width as f32, window_height as f32],
+            CoverageCurve {
+                gamma: game_settings.get_text_gamma(),
+                contrast: game_settings.get_text_contrast(),
+            },
+            if game_settings.get_subpixel_bgr() {
+                SubpixelOrder::Bgr
+            } else {
+                SubpixelOrder::Rgb
+            },
+        );
 
         Controller {
             graphics,
@@ -60,21 +104,136 @@ impl Controller {
         );
     }
 
+    /// Resizes the render target to `width` and `height` physical pixels and
+    /// lays every text out again for the new size and scale factor.
+    pub fn resize(&mut self, width: u32, height: u32, scale_factor: f64) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.graphics.resize(width, height);
+        let window_size = [width as f32, height as f32];
+        self.graphics.update_queue.push(UpdateInfo {
+            target_buffer: self.resource_manager.font.window_info_buffer.clone(),
+            offset: 0,
+            size: wgpu::BufferSize::new(std::mem::size_of_val(&window_size) as _).unwrap(),
+            content: Rc::new(bytemuck::cast_slice(&window_size).to_vec()),
+        });
+        self.gui_manager.resize(
+            width,
+            height,
+            scale_factor,
+            &mut self.graphics.update_queue,
+            &self.graphics.context,
+        );
+    }
+
+    /// Changes how the coverage of text pixels maps to alpha, for every text
+    /// from the next frame on.
+    pub fn set_coverage_curve(&mut self, coverage_curve: CoverageCurve) {
+        let curve = [coverage_curve.gamma, coverage_curve.contrast];
+        self.graphics.update_queue.push(UpdateInfo {
+            target_buffer: self.resource_manager.font.color_info_buffer.clone(),
+            offset: 0,
+            size: wgpu::BufferSize::new(std::mem::size_of_val(&curve) as _).unwrap(),
+            content: Rc::new(bytemuck::cast_slice(&curve).to_vec()),
+        });
+    }
+
     pub fn draw(&mut self) {
+        self.font_manager.upload_glyphs(
+            &mut self.resource_manager.font,
+            &mut self.graphics.update_queue,
+            &self.graphics.context,
+        );
         self.gui_manager
             .draw_queue(&self.resource_manager, &mut self.graphics.draw_queue);
         self.graphics.draw();
     }
 
     pub fn preprocess(&mut self) {
-        self.gui_manager.add_text(
+        self.add_text(
             "FPS: ".to_string(),
-            200.0,
+            [20.0, 20.0],
+            TextStyle {
+                font_size: 200.0,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Adds a text at `position` in logical pixels from the top left corner
+    /// of the window, returns its index.
+    pub fn add_text(&mut self, content: String, position: [f32; 2], style: TextStyle) -> usize {
+        self.gui_manager.add_text(
+            content,
+            position,
+            style,
             self.font_manager.clone(),
             &self.graphics.context,
         )
     }
 
+    pub fn move_text(&mut self, index: usize, position: [f32; 2], anchor: TextAnchor) {
+        self.gui_manager.move_text(
+            index,
+            position,
+            anchor,
+            &mut self.graphics.update_queue,
+            &self.graphics.context,
+        );
+    }
+
+    /// Overrides the style of parts of the text at `index`, see [`TextSpan`].
+    pub fn set_text_spans(&mut self, index: usize, spans: Vec<TextSpan>) {
+        self.gui_manager.set_text_spans(
+            index,
+            spans,
+            &mut self.graphics.update_queue,
+            &self.graphics.context,
+        );
+    }
+
+    /// Size `content` would have as a text with `style`, in logical pixels,
+    /// e.g. to center it or lay other things out around it before adding it.
+    pub fn measure_text(&self, content: &str, style: &TextStyle) -> TextMetrics {
+        self.gui_manager
+            .measure_text(content, style, &self.font_manager)
+    }
+
+    /// Size of the text at `index` and boxes of its glyphs, in logical
+    /// pixels.
+    pub fn text_metrics(&self, index: usize) -> TextMetrics {
+        self.gui_manager.text_metrics(index)
+    }
+
+    /// Caret before byte `char_index` of the text at `index`, in logical
+    /// pixels from the top left corner of the window.
+    pub fn text_caret(&self, index: usize, char_index: usize) -> CaretRect {
+        self.gui_manager.caret_rect(index, char_index)
+    }
+
+    /// Byte index of the text at `index` where a click at `point`, in logical
+    /// pixels, puts the caret.
+    pub fn text_hit_test(&self, index: usize, point: [f32; 2]) -> usize {
+        self.gui_manager.hit_test(index, point)
+    }
+
+    /// Characters of the text at `index` that the font has no glyph for.
+    pub fn missing_chars(&self, index: usize) -> &[char] {
+        self.gui_manager.missing_chars(index)
+    }
+
+    /// Saves the last drawn frame as a PNG, only for headless controllers.
+    pub fn save_frame<P: AsRef<Path>>(&self, path: P) {
+        self.graphics.save_frame(path);
+    }
+
+    /// RGBA pixels of the last drawn frame row by row, `None` unless the
+    /// controller is headless.
+    pub fn read_frame(&self) -> Option<Vec<u8>> {
+        self.graphics.read_frame()
+    }
+
     pub fn exit(&self) {
         self.settings.save();
     }
@@ -1,5 +1,8 @@
 use super::{
-    font_manager::{font_graphics::FontGraphics, FontManager},
+    font_manager::{
+        font_graphics::{CoverageCurve, FontGraphics, SubpixelOrder},
+        FontManager,
+    },
     graphics::GpuContext,
 };
 
@@ -8,8 +11,14 @@ pub struct ResourceManager {
 }
 
 impl ResourceManager {
-    pub fn new(font_manager: &FontManager, gpu_context: &GpuContext) -> Self {
-        let font = font_manager.prepare(gpu_context);
+    pub fn new(
+        font_manager: &FontManager,
+        gpu_context: &GpuContext,
+        window_size: [f32; 2],
+        coverage_curve: CoverageCurve,
+        subpixel_order: SubpixelOrder,
+    ) -> Self {
+        let font = font_manager.prepare(gpu_context, window_size, coverage_curve, subpixel_order);
 
         ResourceManager { font }
     }
@@ -1,12 +1,6 @@
 use std::rc::Rc;
 
-use settings::GameSettings;
-
-use crate::core::Controller;
-
-mod core;
-mod settings;
-mod utils;
+use gputext::{core::Controller, settings::GameSettings};
 
 fn main() {
     env_logger::init();
@@ -18,7 +12,7 @@ fn main() {
             game_settings.get_window_height(),
         ))
         .with_title(game_settings.get_window_title())
-        .with_resizable(false)
+        .with_resizable(true)
         .with_fullscreen(Some(winit::window::Fullscreen::Borderless(None)))
         .build(&event_loop)
         .unwrap();
@@ -31,6 +25,15 @@ fn main() {
                     controller.exit();
                     control_flow.set_exit();
                 }
+                winit::event::WindowEvent::Resized(size) => {
+                    controller.resize(size.width, size.height, window.scale_factor());
+                }
+                winit::event::WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                } => {
+                    controller.resize(new_inner_size.width, new_inner_size.height, scale_factor);
+                }
                 winit::event::WindowEvent::KeyboardInput {
                     input:
                         winit::event::KeyboardInput {
@@ -44,6 +47,15 @@ fn main() {
                         controller.exit();
                         control_flow.set_exit();
                     }
+                    // the resulting resize event updates the controller
+                    winit::event::VirtualKeyCode::F11 => {
+                        if window.fullscreen().is_some() {
+                            window.set_fullscreen(None);
+                        } else {
+                            window
+                                .set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+                        }
+                    }
                     _ => {}
                 },
                 // TODO: impl these
@@ -4,7 +4,7 @@ use std::path::Path;
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use self::window_setting::{window_title, WindowSetting};
+use self::window_setting::{font_list, srgb_surface, text_gamma, window_title, WindowSetting};
 
 const PLAYER_SETTING_FILE: &str = "player_setting.toml";
 const ENGINE_SETTING_FILE: &str = "engine_setting.toml";
@@ -39,6 +39,23 @@ pub struct GameSettings {
 struct GameEngineSettings {
     #[serde(default = "window_title")]
     window_title: String,
+    /// Font files in `data`, in fallback order. `file#face` picks a face of a
+    /// collection by index or name
+    #[serde(default = "font_list")]
+    fonts: Vec<String>,
+    /// Draws into an sRGB surface, blending in linear light, or into a linear
+    /// one, blending the stored sRGB values. Colors look the same on both
+    #[serde(default = "srgb_surface")]
+    srgb_surface: bool,
+    /// Coverage is raised to `1 / text_gamma`, 1 keeps the exact coverage
+    #[serde(default = "text_gamma")]
+    text_gamma: f32,
+    /// Thickens thin strokes, 0 leaves the coverage as is
+    #[serde(default)]
+    text_contrast: f32,
+    /// Subpixel text assumes RGB stripes unless this is set
+    #[serde(default)]
+    subpixel_bgr: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
@@ -104,6 +121,41 @@ impl GameSettings {
     pub fn get_window_title(&self) -> String {
         self.engine_settings.window_title.clone()
     }
+
+    #[inline]
+    pub fn get_srgb_surface(&self) -> bool {
+        self.engine_settings.srgb_surface
+    }
+
+    #[inline]
+    pub fn get_text_gamma(&self) -> f32 {
+        self.engine_settings.text_gamma
+    }
+
+    #[inline]
+    pub fn get_text_contrast(&self) -> f32 {
+        self.engine_settings.text_contrast
+    }
+
+    #[inline]
+    pub fn get_subpixel_bgr(&self) -> bool {
+        self.engine_settings.subpixel_bgr
+    }
+
+    #[inline]
+    pub fn get_font_list(&self) -> Vec<String> {
+        self.engine_settings.fonts.clone()
+    }
+
+    pub fn set_window_size(&mut self, width: u32, height: u32) {
+        self.player_settings.window_setting = WindowSetting(width, height);
+        self.has_changed = true;
+    }
+
+    pub fn set_font_list(&mut self, fonts: Vec<String>) {
+        self.engine_settings.fonts = fonts;
+        self.has_changed = true;
+    }
 }
 
 // https://serde.rs/attr-default.html
@@ -111,6 +163,11 @@ impl Default for GameEngineSettings {
     fn default() -> Self {
         Self {
             window_title: window_title(),
+            fonts: font_list(),
+            srgb_surface: srgb_surface(),
+            text_gamma: text_gamma(),
+            text_contrast: 0.0,
+            subpixel_bgr: false,
         }
     }
 }
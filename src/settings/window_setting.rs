@@ -5,6 +5,18 @@ pub fn window_title() -> String {
     "Game".to_string()
 }
 
+pub fn font_list() -> Vec<String> {
+    vec!["chi1.ttf".to_string()]
+}
+
+pub fn srgb_surface() -> bool {
+    true
+}
+
+pub fn text_gamma() -> f32 {
+    2.0
+}
+
 impl Default for WindowSetting {
     fn default() -> Self {
         WindowSetting(1920, 1080)
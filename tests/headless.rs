@@ -0,0 +1,261 @@
+use std::{path::Path, rc::Rc};
+
+use gputext::{
+    core::{
+        font_manager::{
+            font_graphics::{Antialiasing, CoverageCurve, FillRule},
+            font_info_saving::{FontDrawingData, PreprocessParams},
+            font_rasterizer::pixel_coverage,
+            get_glyph_drawing_data,
+        },
+        gui_manager::text::{TextAnchor, TextOutline, TextStyle},
+        Controller,
+    },
+    settings::GameSettings,
+};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 96;
+
+fn headless_controller() -> Controller {
+    let mut settings = GameSettings::new();
+    settings.set_font_list(vec!["eng1.ttf".to_string()]);
+    settings.set_window_size(WIDTH, HEIGHT);
+    Controller::new_headless(Rc::new(settings))
+}
+
+/// Renders `content` with `data/eng1.ttf` into an offscreen target.
+fn render(content: &str) -> Vec<u8> {
+    let mut controller = headless_controller();
+    controller.add_text(
+        content.to_string(),
+        [WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0],
+        TextStyle {
+            font_size: 40.0,
+            anchor: TextAnchor::Center,
+            ..Default::default()
+        },
+    );
+    controller.draw();
+    controller.read_frame().unwrap()
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn read_png(path: &Path) -> Vec<u8> {
+    let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!([info.width, info.height], [WIDTH, HEIGHT]);
+    assert_eq!(info.color_type, png::ColorType::Rgba);
+    pixels.truncate(info.buffer_size());
+    pixels
+}
+
+fn write_png(path: &Path, pixels: &[u8]) {
+    let file = std::fs::File::create(path).unwrap();
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(pixels)
+        .unwrap();
+}
+
+/// Compares a frame with the reference in `tests/golden`. Adapters round
+/// coverage a little differently, so channels may be off by a few levels and
+/// a handful of edge pixels by more. `UPDATE_GOLDEN=1` rewrites the reference.
+fn assert_matches_golden(name: &str, pixels: &[u8]) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_png(&path, pixels);
+        return;
+    }
+    let golden = read_png(&path);
+    assert_eq!(pixels.len(), golden.len());
+    let differences: Vec<u8> = pixels
+        .iter()
+        .zip(golden.iter())
+        .map(|(a, b)| a.abs_diff(*b))
+        .collect();
+    let far_off = differences.iter().filter(|&&diff| diff > 8).count();
+    let largest = differences.iter().copied().max().unwrap_or(0);
+    assert!(
+        far_off <= differences.len() / 1000 && largest <= 64,
+        "{} differs from the reference: {} channels off by more than 8, at most by {}",
+        name,
+        far_off,
+        largest
+    );
+}
+
+#[test]
+fn text_matches_the_reference() {
+    let pixels = render("Hello, World!");
+    // something was drawn at all
+    assert!(pixels.chunks(4).any(|pixel| pixel[0] == 255));
+    assert_matches_golden("hello_world.png", &pixels);
+}
+
+#[test]
+fn gpu_matches_the_cpu_rasterizer() {
+    let content = "| O g";
+    let font_size = 48.0;
+    let position = [16.0, 8.0];
+    let mut controller = headless_controller();
+    let index = controller.add_text(
+        content.to_string(),
+        position,
+        TextStyle {
+            font_size,
+            ..Default::default()
+        },
+    );
+    controller.draw();
+    let pixels = controller.read_frame().unwrap();
+
+    let font_file =
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("data/eng1.ttf")).unwrap();
+    let face = owned_ttf_parser::Face::parse(&font_file, 0).unwrap();
+    let glyph_rects = controller.text_metrics(index).glyph_rects;
+    assert_eq!(glyph_rects.len(), 3);
+    for rect in glyph_rects {
+        let c = content[rect.cluster..].chars().next().unwrap();
+        let glyph_id = face.glyph_index(c).unwrap().0;
+        let mut drawing_data = FontDrawingData::default();
+        drawing_data.push_glyph(get_glyph_drawing_data(
+            &face,
+            glyph_id,
+            &PreprocessParams::default(),
+        ));
+        let glyph_data = &drawing_data.font_data[0];
+        let min = [position[0] + rect.min[0], position[1] + rect.min[1]];
+        let max = [position[0] + rect.max[0], position[1] + rect.max[1]];
+        let mut largest: f32 = 0.0;
+        let mut covered = 0;
+        for y in (min[1] as u32 - 2)..(max[1] as u32 + 3) {
+            for x in (min[0] as u32 - 2)..(max[0] as u32 + 3) {
+                let center = [x as f32 + 0.5, y as f32 + 0.5];
+                // vs_main draws the bounding box grown by half a pixel
+                let inside_quad = center[0] > min[0] - 0.5
+                    && center[0] < max[0] + 0.5
+                    && center[1] > min[1] - 0.5
+                    && center[1] < max[1] + 0.5;
+                let expected = if inside_quad {
+                    pixel_coverage(
+                        &drawing_data,
+                        glyph_data,
+                        [
+                            (center[0] - min[0]) / font_size,
+                            (max[1] - center[1]) / font_size,
+                        ],
+                        font_size,
+                        FillRule::NonZero,
+                        CoverageCurve::default(),
+                    )
+                } else {
+                    0.0
+                };
+                let actual = srgb_to_linear(pixels[((y * WIDTH + x) * 4) as usize]);
+                largest = largest.max((actual - expected).abs());
+                if expected == 1.0 {
+                    covered += 1;
+                }
+            }
+        }
+        assert!(covered > 0, "{:?} has no fully covered pixel", c);
+        assert!(largest < 0.03, "{:?} is off by {}", c, largest);
+    }
+}
+
+#[test]
+fn outlines_follow_flat_edges() {
+    let content = "-ET";
+    let position = [16.0, 8.0];
+    let mut controller = headless_controller();
+    let index = controller.add_text(
+        content.to_string(),
+        position,
+        TextStyle {
+            font_size: 48.0,
+            outline: Some(TextOutline {
+                width: 3.0,
+                color: [1.0, 0.0, 0.0, 1.0],
+            }),
+            ..Default::default()
+        },
+    );
+    controller.draw();
+    let pixels = controller.read_frame().unwrap();
+
+    // the middles of the tops and bottoms of these glyphs are flat
+    for rect in controller.text_metrics(index).glyph_rects {
+        let c = content[rect.cluster..].chars().next().unwrap();
+        let x = position[0] + (rect.min[0] + rect.max[0]) / 2.0;
+        for y in [
+            position[1] + rect.min[1] - 1.5,
+            position[1] + rect.max[1] + 1.5,
+        ] {
+            let pixel = &pixels[((y as u32 * WIDTH + x as u32) * 4) as usize..][..4];
+            assert!(
+                pixel[0] > 200 && pixel[1] < 50,
+                "no outline on {:?} at y {}: {:?}",
+                c,
+                y,
+                pixel
+            );
+        }
+    }
+}
+
+#[test]
+fn overlapping_subpixel_glyphs_blend_once() {
+    let position = [16.0, 8.0];
+    let mut controller = headless_controller();
+    // lines closer than the bars are tall, the bars overlap
+    let index = controller.add_text(
+        "|\n|".to_string(),
+        position,
+        TextStyle {
+            font_size: 40.0,
+            line_spacing: 0.5,
+            color: [0.4, 0.4, 0.4, 1.0],
+            antialiasing: Antialiasing::Subpixel,
+            ..Default::default()
+        },
+    );
+    controller.draw();
+    let pixels = controller.read_frame().unwrap();
+
+    let glyph_rects = controller.text_metrics(index).glyph_rects;
+    let (first, second) = (glyph_rects[0], glyph_rects[1]);
+    assert!(second.min[1] + 4.0 < first.max[1]);
+    let pixel = |x: f32, y: f32| {
+        let (x, y) = ((position[0] + x) as u32, (position[1] + y) as u32);
+        &pixels[((y * WIDTH + x) * 4) as usize..][..3]
+    };
+    let x = (first.min[0] + first.max[0]) / 2.0;
+    let alone = pixel(x, first.min[1] + 2.0);
+    let overlap = pixel(x, (second.min[1] + first.max[1]) / 2.0);
+    assert!(alone[1] > 0, "nothing drawn");
+    for channel in 0..3 {
+        assert!(
+            alone[channel].abs_diff(overlap[channel]) <= 2,
+            "{:?} alone, {:?} where the glyphs overlap",
+            alone,
+            overlap
+        );
+    }
+}